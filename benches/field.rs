@@ -0,0 +1,80 @@
+//! Times the incremental update of the field, when a few charges change
+//! on a world whose field is already calculated
+//! Run with `cargo bench`
+
+extern crate estatic;
+extern crate nalgebra;
+
+use estatic::world::{Charge, FieldLaw, Mode, World};
+use nalgebra::Vector2;
+
+use std::time::Instant;
+
+/// The number of timed updates of every benchmark
+const ITERATIONS: u32 = 50;
+
+/// Print the mean time of `update` over `ITERATIONS` runs, after a first untimed one
+fn bench<F: FnMut()>(name: &str, mut update: F) {
+    update();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        update();
+    }
+    let elapsed = start.elapsed();
+    let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+
+    println!("{:<48} {:>9.3} ms", name, seconds * 1e3 / ITERATIONS as f64);
+}
+
+/// A calculated world of 200 × 200 tiles at resolution 2 (600 × 600 field cells),
+/// with a row of charged tiles in its middle
+fn world(law: FieldLaw, mode: Mode) -> World {
+    let mut world = World::new_empty(200, 200, 2);
+    world.set_law(law);
+    world.set_mode(mode);
+    for x in 80..120 {
+        world.update_tile(100, x, 100);
+    }
+    world.calculate_field();
+    world
+}
+
+fn main() {
+    let mut single = world(FieldLaw::PointCharges, Mode::Electrostatic);
+    let mut charge = 127;
+    bench("flip a tile (point charges)", || {
+        charge = -charge;
+        single.update_tile(charge, 100, 150);
+        single.calculate_field();
+    });
+
+    let mut row = world(FieldLaw::PointCharges, Mode::Electrostatic);
+    let mut charge = 127;
+    bench("flip a row of 32 tiles (point charges)", || {
+        charge = -charge;
+        for x in 84..116 {
+            row.update_tile(charge, x, 50);
+        }
+        row.calculate_field();
+    });
+
+    let mut lines = world(FieldLaw::LineCharges, Mode::Electrostatic);
+    let index = lines.add_charge(Charge::new(Vector2::new(50.5, 50.5), 50.0));
+    lines.calculate_field();
+    let mut step = 0.25;
+    bench("move a free charge (line charges)", || {
+        step = -step;
+        let position = lines.charges[index].position + Vector2::new(step, 0.0);
+        lines.move_charge(index, position);
+        lines.calculate_field();
+    });
+
+    let mut currents = world(FieldLaw::PointCharges, Mode::Magnetostatic);
+    let mut current = 127;
+    bench("flip a wire (magnetostatics)", || {
+        current = -current;
+        currents.update_tile(current, 150, 100);
+        currents.calculate_field();
+    });
+}
@@ -4,8 +4,11 @@ extern crate gfx;
 extern crate image;
 extern crate nalgebra;
 extern crate piston_window;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 
 type Vector = nalgebra::Vector2<f64>;
 
+pub mod parallel;
 pub mod viewer;
 pub mod world;
@@ -0,0 +1,63 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Call `f` on every row with its index
+/// The rows are processed in parallel when the `parallel` feature is enabled
+pub fn for_each_row<T, F>(rows: &mut [T], f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut T) + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    {
+        rows.par_iter_mut().enumerate().for_each(|(y, row)| f(y, row));
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        for (y, row) in rows.iter_mut().enumerate() {
+            f(y, row);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+/// The progress of a long calculation, which can be read from another thread
+pub struct Progress {
+    done: AtomicUsize,
+    total: AtomicUsize,
+}
+
+impl Progress {
+    pub fn new() -> Progress {
+        Progress::default()
+    }
+
+    /// Start a new step of the calculation, made of `total` parts
+    pub fn start(&self, total: usize) {
+        self.done.store(0, Ordering::SeqCst);
+        self.total.store(total, Ordering::SeqCst);
+    }
+
+    /// Mark `amount` parts as done
+    pub fn advance(&self, amount: usize) {
+        self.done.fetch_add(amount, Ordering::SeqCst);
+    }
+
+    /// Mark the current step as done
+    pub fn finish(&self) {
+        self.done.store(self.total.load(Ordering::SeqCst), Ordering::SeqCst);
+    }
+
+    /// How much of the current step is done, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        let total = self.total.load(Ordering::SeqCst);
+        if total == 0 {
+            1.0
+        } else {
+            self.done.load(Ordering::SeqCst) as f64 / total as f64
+        }
+    }
+}
@@ -1,11 +1,59 @@
 use piston_window::*;
 use viewer::input;
 use viewer::inputbox::InputBox;
+use viewer::label::Label;
+use world::boundaries::{Boundary, Edge};
+use world::capacitance::ConductorBody;
+use world::chunks::Chunks;
+use world::lines::ChargeRegion;
+use world::mirrors::Mirror;
+use world::units::{format_si, per_length};
+use world::viewport::{ViewField, Viewport};
 
 use super::*;
 
 use image;
-use image::ImageBuffer;
+use image::{ImageBuffer, RgbaImage};
+use parallel::for_each_row;
+
+use std::cmp::Ordering;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+/// The number of points used to measure the error of the Barnes–Hut evaluator
+const ERROR_SAMPLES: usize = 100;
+/// The length of the arrow of the strongest force, in tiles
+const FORCE_ARROW_LENGTH: f64 = 4.0;
+/// The charge of a conductor tile drawn with the strongest color
+const SURFACE_CHARGE_SCALE: f64 = 5.0;
+/// The potential and the field drawn with the strongest color
+const COLOR_SCALE: f64 = 255.0;
+/// About the length of the scale bar, in pixels
+const SCALE_BAR_LENGTH: f64 = 100.0;
+/// The largest side of the image of a bounded world, in pixels
+/// Bigger views have a pixel for every block of tiles
+const MAX_IMAGE_SIZE: usize = 2048;
+
+/// The results of a calculation done in the background
+struct Calculation {
+    world: World,
+    lines: Vec<Vec<Vector>>,
+    // The equipotentials and their potentials
+    contours: Vec<(f64, Vec<Vec<Vector>>)>,
+    // The force on every group of charges
+    forces: Vec<(ChargeRegion, Vector)>,
+    // The energy of the charges, when the forces are shown
+    energy: Option<f64>,
+    // The conductors with their charge, and their capacitance matrix
+    conductors: Vec<ConductorBody>,
+    capacitance: Vec<Vec<f64>>,
+    // The image charges replacing the mirrors, when they are shown
+    images: Vec<(Vector, f64)>,
+    image: RgbaImage,
+    // The part of the world shown by the image
+    viewport: Viewport,
+    status: String,
+}
 
 pub fn render_loop(mut view: ViewState) {
     let mut input_state = input::InputState::new();
@@ -15,9 +63,21 @@ pub fn render_loop(mut view: ViewState) {
         .build()
         .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
 
-    // Create the texture to render the world on
-    let mut texture = empty_texture(&mut window.factory, view.world.width, view.world.height);
+    // Create the texture to render the world on, it gets the size of the first image
+    let mut texture_size = (1, 1);
+    let mut texture = empty_texture(&mut window.factory, texture_size.0, texture_size.1);
+    // The part of the world shown by the texture, and the part of the canvas being calculated
+    let mut map_viewport = None;
+    let mut calculated_viewport = None;
     let mut field_lines = Vec::new();
+    let mut contours = Vec::new();
+    let mut forces = Vec::new();
+    let mut conductors = Vec::new();
+    let mut capacitance = Vec::new();
+    let mut images = Vec::new();
+    let mut energy_value = None;
+    // The calculation running in the background, if any
+    let mut calculation: Option<Receiver<Calculation>> = None;
 
     // Init the GUI
     let mut width_input = InputBox::new(window.factory.clone(), (10.0, 24.0))
@@ -29,6 +89,43 @@ pub fn render_loop(mut view: ViewState) {
     let mut resolution_input = InputBox::new(window.factory.clone(), (10.0, 76.0))
         .description("Resolution")
         .value(view.world.resolution());
+    let mut voltage_input = InputBox::new(window.factory.clone(), (10.0, 102.0))
+        .description("Voltage (V)")
+        .value(view.voltage);
+    let mut permittivity_input = InputBox::new(window.factory.clone(), (10.0, 128.0))
+        .description("Permittivity")
+        .value(view.permittivity);
+    let mut point_charge_input = InputBox::new(window.factory.clone(), (10.0, 154.0))
+        .description("Point charge")
+        .value(view.point_charge);
+    let mut theta_input = InputBox::new(window.factory.clone(), (10.0, 180.0))
+        .description("Theta")
+        .value(view.theta);
+    let mut mass_input = InputBox::new(window.factory.clone(), (10.0, 206.0))
+        .description("Mass")
+        .value(view.mass);
+    let mut time_step_input = InputBox::new(window.factory.clone(), (10.0, 232.0))
+        .description("Time step")
+        .value(view.world.dynamics.time_step);
+    let mut contour_spacing_input = InputBox::new(window.factory.clone(), (10.0, 258.0))
+        .description("Contour spacing")
+        .value(view.contour_spacing);
+    let mut line_tolerance_input = InputBox::new(window.factory.clone(), (10.0, 284.0))
+        .description("Line tolerance")
+        .value(view.world.line_settings.tolerance);
+    let mut cell_size_input = InputBox::new(window.factory.clone(), (10.0, 310.0))
+        .description("Cell size (m)")
+        .value(view.world.units.cell_size);
+    let mut status = Label::new(window.factory.clone(), (10.0, 336.0));
+    let mut energy = Label::new(window.factory.clone(), (10.0, 362.0));
+    let mut flux = Label::new(window.factory.clone(), (10.0, 388.0));
+    let mut work = Label::new(window.factory.clone(), (10.0, 414.0));
+    // Shows the charges and the capacitance of the conductors, one line each
+    let mut results = Label::new(window.factory.clone(), (10.0, 440.0));
+    // Shows the length of the scale bar and the scale of the colors
+    let mut legend = Label::new(window.factory.clone(), (0.0, 0.0));
+    // Shows the potential of the equipotentials
+    let mut contour_label = Label::new(window.factory.clone(), (0.0, 0.0));
 
     let mut width = view.world.width;
     let mut height = view.world.height;
@@ -37,15 +134,66 @@ pub fn render_loop(mut view: ViewState) {
     while let Some(e) = window.next() {
         input_state.event(&e);
 
-        // Set the variables from the UI
-        width_input.input(&mut width);
-        height_input.input(&mut height);
+        // Set the variables from the UI (the canvas has no dimensions)
+        if !view.world.is_unbounded() {
+            width_input.input(&mut width);
+            height_input.input(&mut height);
+        }
         resolution_input.input(&mut resolution);
+        voltage_input.input(&mut view.voltage);
+        // The permittivity, the cell size and the mass divide the fields and the forces,
+        // and the time step must go forwards, so they must be positive
+        let positive = |value: &f64| value.is_finite() && *value > 0.0;
+        if !permittivity_input.input_checked(&mut view.permittivity, positive) {
+            view.message = Some("The permittivity must be positive".to_string());
+        }
+        point_charge_input.input(&mut view.point_charge);
+        // Theta is compared with the size of a node over its distance
+        let non_negative = |value: &f64| value.is_finite() && *value >= 0.0;
+        if !theta_input.input_checked(&mut view.theta, non_negative) {
+            view.message = Some("Theta can't be negative".to_string());
+        }
+        if !mass_input.input_checked(&mut view.mass, positive) {
+            view.message = Some("The mass must be positive".to_string());
+        }
+        if !time_step_input.input_checked(&mut view.world.dynamics.time_step, positive) {
+            view.message = Some("The time step must be positive".to_string());
+        }
+        if !cell_size_input.input_checked(&mut view.world.units.cell_size, positive) {
+            view.message = Some("The cell size must be a positive length".to_string());
+        }
+
+        // When the user inputted a new tolerance trace the lines again
+        let line_tolerance = view.world.line_settings.tolerance;
+        line_tolerance_input.input(&mut view.world.line_settings.tolerance);
+        if view.world.line_settings.tolerance != line_tolerance {
+            view.changed = true;
+        }
+
+        // When the user inputted a new spacing draw the equipotentials again
+        let contour_spacing = view.contour_spacing;
+        contour_spacing_input.input(&mut view.contour_spacing);
+        if view.contour_spacing != contour_spacing
+            && view.draw_settings.contains(DrawSets::EQUIPOTENTIALS)
+        {
+            view.changed = true;
+        }
 
+        // When the user switched to or from the canvas replace the world
+        if view.unbounded != view.world.is_unbounded() {
+            view.world = if view.unbounded {
+                World::new_unbounded(resolution)
+            } else {
+                World::new_empty(width, height, resolution)
+            };
+            map_viewport = None;
+            view.center_view();
+            view.changed = true;
+        }
         // When the user has inputted new dimentions update the world
-        if width != view.world.width || height != view.world.height {
+        if !view.unbounded && (width != view.world.width || height != view.world.height) {
             view.world = World::new_empty(width, height, resolution);
-            texture = empty_texture(&mut window.factory, width, height);
+            map_viewport = None;
             view.changed = true;
         }
         // When the user inputted a new resolution update the world
@@ -54,35 +202,106 @@ pub fn render_loop(mut view: ViewState) {
             view.changed = true;
         }
 
-        // If the world has been changed update the view
-        if view.changed {
-            // Calculate new field and field_lines
-            view.world.calculate_field();
-            field_lines = view.world.calculate_lines();
+        // When the user inputted a new accuracy update the evaluator
+        if let Evaluator::BarnesHut { theta } = view.world.evaluator() {
+            if theta != view.theta {
+                view.world.set_evaluator(Evaluator::BarnesHut { theta: view.theta });
+                view.changed = true;
+            }
+        }
+
+        // Only the field of the visible part of the world is evaluated,
+        // the canvas is evaluated again on every pixel whenever the view moves
+        let viewport = view.viewport();
+        if view.world.is_unbounded() {
+            if calculated_viewport != Some(viewport) {
+                view.changed = true;
+            }
+        } else if view.world.set_view(&viewport.min, &viewport.max) {
+            view.changed = true;
+        }
+
+        // Move the charges, one step for every calculated view
+        if view.playing && calculation.is_none() && view.world.step() {
+            view.changed = true;
+        }
 
-            // Render the new world to the texture
-            update_texture(
-                &view.world,
+        // If the world has been changed calculate the new view in the background
+        // (the changes made in the meantime wait for the next calculation)
+        if view.changed && calculation.is_none() {
+            let world = view.world.start_calculation();
+            calculated_viewport = if world.is_unbounded() {
+                Some(viewport)
+            } else {
+                None
+            };
+            calculation = Some(start_calculation(
+                world,
                 view.draw_settings,
-                &mut texture,
-                &mut window.encoder,
-            );
+                view.contour_spacing,
+                calculated_viewport,
+            ));
             view.changed = false;
         }
 
+        // Show the results of the calculation once it's done
+        let state = calculation.as_ref().map(|receiver| receiver.try_recv());
+        match state {
+            Some(Ok(result)) => {
+                calculation = None;
+
+                // The results are ignored if the world was reset in the meantime
+                if view.world.finish_calculation(result.world) {
+                    field_lines = result.lines;
+                    contours = result.contours;
+                    forces = result.forces;
+                    conductors = result.conductors;
+                    capacitance = result.capacitance;
+                    energy_value = result.energy;
+                    images = result.images;
+                    view.update_trajectories();
+                    view.update_flux();
+                    view.update_line_integral();
+                    status.text = result.status;
+                    // Apply the image to the texture, the image has the size of the part
+                    // of the world seen when it was calculated
+                    let image_size = result.image.dimensions();
+                    if image_size != texture_size {
+                        texture_size = image_size;
+                        texture = empty_texture(&mut window.factory, image_size.0, image_size.1);
+                    }
+                    texture.update(&mut window.encoder, &result.image).unwrap();
+                    map_viewport = Some(result.viewport);
+                }
+            }
+            // The calculation failed, give up on it
+            Some(Err(TryRecvError::Disconnected)) => calculation = None,
+            _ => {}
+        }
+        if let Some(message) = view.message.take() {
+            status.text = message;
+        }
+
         if let Some(_args) = e.render_args() {
             view.width = window.size().width;
             view.height = window.size().height;
 
             window.draw_2d(&e, |c, g| {
                 clear([1.0, 1.0, 1.0, 1.0], g);
-                let position = view.get_screen_pos(0.0, view.world.height as f64);
+                let units = view.world.units;
+                let law = view.world.law();
+                let mode = view.world.mode();
 
-                // Render the map
-                let trans = c.transform
-                    .trans(position.x, position.y)
-                    .scale(view.scale, view.scale);
-                image(&texture, trans, g);
+                // Render the map, the image covers the part of the world it was calculated for
+                if let Some(viewport) = map_viewport {
+                    let pixel = viewport.pixel();
+                    let position = view.get_screen_pos(viewport.min.x, viewport.max.y);
+                    let scale = pixel.x * view.scale;
+                    let trans = c.transform
+                        .trans(position.x, position.y)
+                        .scale(scale, scale);
+                    image(&texture, trans, g);
+                }
 
                 if view.draw_settings.contains(DrawSets::FIELD_LINES) {
                     for field_line in &field_lines {
@@ -100,9 +319,393 @@ pub fn render_loop(mut view: ViewState) {
                     }
                 }
 
-                width_input.update(&mut input_state, &c, g);
-                height_input.update(&mut input_state, &c, g);
+                // Render the equipotentials, labeled with their potential in their middle
+                if view.draw_settings.contains(DrawSets::EQUIPOTENTIALS) {
+                    for &(level, ref polylines) in &contours {
+                        for polyline in polylines {
+                            for pair in polyline.windows(2) {
+                                let pos = view.get_screen_pos(pair[0].x, pair[0].y);
+                                let npos = view.get_screen_pos(pair[1].x, pair[1].y);
+
+                                let line_data = [pos.x, pos.y, npos.x, npos.y];
+                                line([0.2, 0.2, 0.2, 0.6], 1.0, line_data, c.transform, g);
+                            }
+
+                            let middle = polyline[polyline.len() / 2];
+                            let position = view.get_screen_pos(middle.x, middle.y);
+                            if view.in_screen(position.x, position.y) {
+                                let volts = units.potential(law, level);
+                                let text = format_units(mode, level, volts, "V");
+                                contour_label.draw_at(&text, (position.x, position.y), &c, g);
+                            }
+                        }
+                    }
+                }
+
+                // Render the forces on the charges, the strongest one has the longest arrow
+                if view.draw_settings.contains(DrawSets::FORCES) {
+                    let strongest = forces
+                        .iter()
+                        .map(|&(_, force)| force.norm())
+                        .fold(0.0, f64::max);
+
+                    for &(ref region, force) in &forces {
+                        if strongest == 0.0 || force.norm() == 0.0 {
+                            continue;
+                        }
+
+                        let end = region.center + force / strongest * FORCE_ARROW_LENGTH;
+                        let start = view.get_screen_pos(region.center.x, region.center.y);
+                        let end = view.get_screen_pos(end.x, end.y);
+                        Line::new([0.0, 0.5, 0.0, 1.0], 1.0).draw_arrow(
+                            [start.x, start.y, end.x, end.y],
+                            5.0,
+                            &c.draw_state,
+                            c.transform,
+                            g,
+                        );
+                    }
+                }
+
+                // Number the conductors
+                if view.draw_settings.contains(DrawSets::SURFACE_CHARGE) {
+                    for (i, conductor) in conductors.iter().enumerate() {
+                        let (x, y) = conductor.tiles[0];
+                        let position = view.get_screen_pos(x as f64, y as f64);
+                        let text = format!("{}", i + 1);
+                        results.draw_at(&text, (position.x, position.y), &c, g);
+                    }
+                }
+
+                // Render the paths of the test particles
+                for trajectory in &view.trajectories {
+                    for segment in &trajectory.segments {
+                        for pair in segment.windows(2) {
+                            let pos = view.get_screen_pos(pair[0].x, pair[0].y);
+                            let npos = view.get_screen_pos(pair[1].x, pair[1].y);
+
+                            let line_data = [pos.x, pos.y, npos.x, npos.y];
+                            line([0.9, 0.5, 0.0, 1.0], 1.5, line_data, c.transform, g);
+                        }
+                    }
+                }
+
+                // Render the conditions on the edges
+                let boundaries = view.world.boundaries();
+                let (world_width, world_height) =
+                    (view.world.width as f64, view.world.height as f64);
+                let edges = [
+                    (Edge::Left, (0.0, 0.0), (0.0, world_height)),
+                    (Edge::Right, (world_width, 0.0), (world_width, world_height)),
+                    (Edge::Bottom, (0.0, 0.0), (world_width, 0.0)),
+                    (Edge::Top, (0.0, world_height), (world_width, world_height)),
+                ];
+                for &(edge, start, end) in edges.iter() {
+                    let color = match boundaries.get(edge) {
+                        Boundary::Open => continue,
+                        Boundary::Grounded => [0.3, 0.3, 0.3, 1.0],
+                        Boundary::Insulating => [0.9, 0.5, 0.0, 1.0],
+                        Boundary::Periodic => [0.0, 0.6, 0.0, 1.0],
+                    };
+
+                    // The selected edge is drawn wider
+                    let width = if view.edge == Some(edge) { 4.0 } else { 2.0 };
+                    let start = view.get_screen_pos(start.0, start.1);
+                    let end = view.get_screen_pos(end.0, end.1);
+                    line(color, width, [start.x, start.y, end.x, end.y], c.transform, g);
+                }
+
+                // Render the free charges
+                for charge in &view.world.charges {
+                    let position = view.get_screen_pos(charge.position.x, charge.position.y);
+                    let radius = CHARGE_RADIUS * view.scale;
+                    let color = if charge.q > 0.0 {
+                        [0.8, 0.0, 0.0, 1.0]
+                    } else {
+                        [0.0, 0.0, 0.8, 1.0]
+                    };
+
+                    let rectangle = [
+                        position.x - radius,
+                        position.y - radius,
+                        radius * 2.0,
+                        radius * 2.0,
+                    ];
+                    ellipse(color, rectangle, c.transform, g);
+
+                    // The pinned charges have a black border
+                    if charge.pinned {
+                        Ellipse::new_border([0.0, 0.0, 0.0, 1.0], 1.5).draw(
+                            rectangle,
+                            &c.draw_state,
+                            c.transform,
+                            g,
+                        );
+                    }
+                }
+
+                // Render the grounded planes and circles
+                let mirror_color = [0.3, 0.3, 0.3, 1.0];
+                for mirror in view.world.mirrors() {
+                    match *mirror {
+                        Mirror::Plane { point, normal } => {
+                            // The plane is infinite, so it's drawn across the whole world
+                            let direction = Vector::new(-normal.y, normal.x);
+                            let length = world_width + world_height + point.norm();
+                            let start = point - direction * length;
+                            let end = point + direction * length;
+
+                            let start = view.get_screen_pos(start.x, start.y);
+                            let end = view.get_screen_pos(end.x, end.y);
+                            let line_data = [start.x, start.y, end.x, end.y];
+                            line(mirror_color, 2.0, line_data, c.transform, g);
+                        }
+                        Mirror::Circle { center, radius } => {
+                            let position = view.get_screen_pos(center.x, center.y);
+                            let radius = radius * view.scale;
+                            Ellipse::new_border(mirror_color, 1.0).draw(
+                                [
+                                    position.x - radius,
+                                    position.y - radius,
+                                    radius * 2.0,
+                                    radius * 2.0,
+                                ],
+                                &c.draw_state,
+                                c.transform,
+                                g,
+                            );
+                        }
+                    }
+                }
+
+                // Render the (virtual) image charges replacing the mirrors
+                if view.draw_settings.contains(DrawSets::IMAGES) {
+                    for &(position, charge) in &images {
+                        let position = view.get_screen_pos(position.x, position.y);
+                        let radius = CHARGE_RADIUS * view.scale;
+                        let color = if charge > 0.0 {
+                            [0.8, 0.0, 0.0, 0.6]
+                        } else {
+                            [0.0, 0.0, 0.8, 0.6]
+                        };
+
+                        Ellipse::new_border(color, 1.0).draw(
+                            [
+                                position.x - radius,
+                                position.y - radius,
+                                radius * 2.0,
+                                radius * 2.0,
+                            ],
+                            &c.draw_state,
+                            c.transform,
+                            g,
+                        );
+                    }
+                }
+
+                // Render the surface measuring the flux, or the one being drawn
+                let surface = match input::drawn_surface(&view, &input_state) {
+                    Some(surface) => Some(surface),
+                    None => view.surface.clone(),
+                };
+                if let Some(surface) = surface {
+                    let points = surface.points();
+                    for (i, point) in points.iter().enumerate() {
+                        let next = &points[(i + 1) % points.len()];
+                        let pos = view.get_screen_pos(point.x, point.y);
+                        let npos = view.get_screen_pos(next.x, next.y);
+
+                        let line_data = [pos.x, pos.y, npos.x, npos.y];
+                        line([0.5, 0.0, 0.6, 1.0], 1.0, line_data, c.transform, g);
+                    }
+                }
+
+                // Render the path measuring the work, or the one being drawn
+                let path = if view.tool == Tool::Path && view.drag_start.is_some() {
+                    &view.drawn_curve
+                } else {
+                    &view.path
+                };
+                for pair in path.windows(2) {
+                    let pos = view.get_screen_pos(pair[0].x, pair[0].y);
+                    let npos = view.get_screen_pos(pair[1].x, pair[1].y);
+
+                    let line_data = [pos.x, pos.y, npos.x, npos.y];
+                    line([0.0, 0.5, 0.5, 1.0], 1.5, line_data, c.transform, g);
+                }
+
+                // Show the plane, the circle or the launch velocity being placed
+                if let Some(start) = view.drag_start {
+                    let cursor = view.get_world_pos(input_state.cursor.x, input_state.cursor.y);
+                    let radius = (cursor - start).norm() * view.scale;
+                    let start = view.get_screen_pos(start.x, start.y);
+                    let end = input_state.cursor;
+
+                    if view.tool == Tool::Circle {
+                        Ellipse::new_border(mirror_color, 1.0).draw(
+                            [start.x - radius, start.y - radius, radius * 2.0, radius * 2.0],
+                            &c.draw_state,
+                            c.transform,
+                            g,
+                        );
+                    } else if view.tool != Tool::Gauss && view.tool != Tool::Path {
+                        line(mirror_color, 1.0, [start.x, start.y, end.x, end.y], c.transform, g);
+                    }
+                }
+
+                if !view.world.is_unbounded() {
+                    width_input.update(&mut input_state, &c, g);
+                    height_input.update(&mut input_state, &c, g);
+                }
                 resolution_input.update(&mut input_state, &c, g);
+                voltage_input.update(&mut input_state, &c, g);
+                permittivity_input.update(&mut input_state, &c, g);
+                point_charge_input.update(&mut input_state, &c, g);
+                theta_input.update(&mut input_state, &c, g);
+                mass_input.update(&mut input_state, &c, g);
+                time_step_input.update(&mut input_state, &c, g);
+                contour_spacing_input.update(&mut input_state, &c, g);
+                line_tolerance_input.update(&mut input_state, &c, g);
+                cell_size_input.update(&mut input_state, &c, g);
+                status.draw(&c, g);
+                energy.text = match energy_value {
+                    Some(value) => {
+                        let joules = units.energy(law, value);
+                        let unit = per_length(law, "J");
+                        format!("Energy: {}", format_units(mode, value, joules, &unit))
+                    }
+                    None => String::new(),
+                };
+                energy.draw(&c, g);
+
+                // Compare the flux with the enclosed charge
+                flux.text = match view.flux {
+                    Some(report) => {
+                        let expected = match report.expected {
+                            Some(expected) => {
+                                let si = units.flux(law, expected);
+                                format!("Gauss's law: {}", format_units(mode, expected, si, "C/m"))
+                            }
+                            None => "Gauss's law needs line charges".to_string(),
+                        };
+                        format!(
+                            "Flux of D: {}, enclosed charge: {}, {}",
+                            format_units(mode, report.flux, units.flux(law, report.flux), "C/m"),
+                            format_units(
+                                mode,
+                                report.enclosed,
+                                units.charge(law, report.enclosed),
+                                &per_length(law, "C")
+                            ),
+                            expected
+                        )
+                    }
+                    None => String::new(),
+                };
+                flux.draw(&c, g);
+
+                // Compare the work along the path with the potential difference
+                work.text = match view.line_integral {
+                    Some(measure) => {
+                        let voltage = match measure.voltage {
+                            Some(voltage) => {
+                                format_units(mode, voltage, units.potential(law, voltage), "V")
+                            }
+                            None => "-".to_string(),
+                        };
+                        let work = measure.integral * view.point_charge;
+                        format!(
+                            "∫E·dl: {}, V(start) - V(end): {}, work: {}",
+                            format_units(
+                                mode,
+                                measure.integral,
+                                units.potential(law, measure.integral),
+                                "V"
+                            ),
+                            voltage,
+                            format_units(mode, work, units.energy(law, work), &per_length(law, "J"))
+                        )
+                    }
+                    None => String::new(),
+                };
+                work.draw(&c, g);
+
+                // The charge of every conductor, then the rows of the capacitance matrix
+                if view.draw_settings.contains(DrawSets::SURFACE_CHARGE) {
+                    let mut lines: Vec<String> = conductors
+                        .iter()
+                        .enumerate()
+                        .map(|(i, conductor)| {
+                            let (potential, charge) = (conductor.potential, conductor.charge);
+                            format!(
+                                "Conductor {}: V = {}, Q = {}",
+                                i + 1,
+                                format_units(mode, potential, units.potential(law, potential), "V"),
+                                format_units(
+                                    mode,
+                                    charge,
+                                    units.charge(law, charge),
+                                    &per_length(law, "C")
+                                )
+                            )
+                        })
+                        .collect();
+                    if !capacitance.is_empty() {
+                        lines.push("Capacitance:".to_string());
+                    }
+                    for row in &capacitance {
+                        let row: Vec<String> = row
+                            .iter()
+                            .map(|&c| {
+                                let farads = units.capacitance(law, c);
+                                format_units(mode, c, farads, &per_length(law, "F"))
+                            })
+                            .collect();
+                        lines.push(row.join("  "));
+                    }
+
+                    for (i, line) in lines.iter().enumerate() {
+                        results.draw_at(line, (10.0, 440.0 + 26.0 * i as f64), &c, g);
+                    }
+                }
+
+                // Draw a scale bar of a round length in the lower left corner,
+                // with the potential and the field of the strongest colors
+                let bar_meters = round_length(SCALE_BAR_LENGTH / view.scale * units.cell_size);
+                let bar = bar_meters / units.cell_size * view.scale;
+                let (left, bottom) = (10.0, view.height as f64 - 20.0);
+                let bar_color = [0.0, 0.0, 0.0, 1.0];
+                let right = left + bar;
+                line(bar_color, 1.0, [left, bottom, right, bottom], c.transform, g);
+                line(bar_color, 1.0, [left, bottom - 5.0, left, bottom], c.transform, g);
+                line(bar_color, 1.0, [right, bottom - 5.0, right, bottom], c.transform, g);
+                legend.draw_at(&format_si(bar_meters, "m"), (right + 8.0, bottom), &c, g);
+
+                let colors = match mode {
+                    Mode::Electrostatic => format!(
+                        "Unit of charge: 1 {}, strongest colors: {}, {}",
+                        units.charge_unit.symbol(),
+                        format_si(units.potential(law, COLOR_SCALE), "V"),
+                        format_si(units.field(law, COLOR_SCALE), "V/m")
+                    ),
+                    Mode::Magnetostatic => format!(
+                        "Strongest colors: {:.3}, {:.3}",
+                        COLOR_SCALE, COLOR_SCALE
+                    ),
+                };
+                legend.draw_at(&colors, (left, bottom - 12.0), &c, g);
+
+                // Show the progress of the calculation running in the background
+                if calculation.is_some() {
+                    let fraction = view.world.progress.fraction();
+                    let (width, height) = (view.width as f64, view.height as f64);
+                    rectangle(
+                        [0.2, 0.4, 0.9, 0.8],
+                        [0.0, height - 6.0, width * fraction, 6.0],
+                        c.transform,
+                        g,
+                    );
+                }
 
                 input::handle_input(&mut view, &mut input_state);
                 input_state.processed();
@@ -111,6 +714,108 @@ pub fn render_loop(mut view: ViewState) {
     }
 }
 
+/// Calculate the field, the field lines and the image of `world` on another thread
+/// The image of the canvas shows `viewport`, evaluated on every pixel,
+/// and the image of a bounded world shows the seen chunks
+fn start_calculation(
+    mut world: World,
+    settings: DrawSets,
+    contour_spacing: f64,
+    viewport: Option<Viewport>,
+) -> Receiver<Calculation> {
+    let (sender, receiver) = channel();
+
+    thread::spawn(move || {
+        world.calculate_field();
+        let view_field = viewport.map(|viewport| world.evaluate_view(viewport));
+        let lines = world.calculate_lines();
+        let contours = match view_field {
+            _ if !settings.contains(DrawSets::EQUIPOTENTIALS) => Vec::new(),
+            Some(ref field) => field.even_equipotentials(contour_spacing),
+            None => world.even_equipotentials(contour_spacing),
+        };
+        let (forces, energy) = if settings.contains(DrawSets::FORCES) {
+            (world.region_forces(), Some(world.energy()))
+        } else {
+            (Vec::new(), None)
+        };
+
+        // Report the accuracy of the approximated field
+        let status = match world.tree_error(ERROR_SAMPLES) {
+            _ if world.too_big_to_solve() => {
+                "Too big to solve the materials, open the edges or remove the planes".to_string()
+            }
+            Some(report) => format!(
+                "Barnes-Hut error: max {:.2}%, mean {:.2}%",
+                report.max_error * 100.0,
+                report.mean_error * 100.0
+            ),
+            None => String::new(),
+        };
+
+        let (conductors, capacitance) = if settings.contains(DrawSets::SURFACE_CHARGE) {
+            (world.conductor_bodies(), world.capacitance())
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        let images = if settings.contains(DrawSets::IMAGES) {
+            world.image_charges()
+        } else {
+            Vec::new()
+        };
+
+        let (image, image_viewport) = match view_field {
+            Some(ref field) => (render_view(&world, field, settings), field.viewport),
+            None => render_image(&world, settings),
+        };
+
+        // Nobody is waiting for the results if the window was closed
+        let _ = sender.send(Calculation {
+            world,
+            lines,
+            contours,
+            forces,
+            energy,
+            conductors,
+            capacitance,
+            images,
+            image,
+            viewport: image_viewport,
+            status,
+        });
+    });
+
+    receiver
+}
+
+/// `value` with `unit`, from `si` its value in SI units,
+/// or without units in magnetostatics where the values have none
+fn format_units(mode: Mode, value: f64, si: f64, unit: &str) -> String {
+    match mode {
+        Mode::Electrostatic => format_si(si, unit),
+        Mode::Magnetostatic => format!("{:.3}", value),
+    }
+}
+
+/// The length of 1, 2 or 5 times a power of ten closest to `length`
+/// (`length` itself when it isn't a positive length)
+fn round_length(length: f64) -> f64 {
+    if !(length.is_finite() && length > 0.0) {
+        return length;
+    }
+
+    let power = 10f64.powf(length.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|&factor| factor * power)
+        .min_by(|a, b| {
+            let (a, b) = ((a - length).abs(), (b - length).abs());
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        })
+        .unwrap_or(length)
+}
+
 /// Create a new empty texture with `Nearest` filtering
 fn empty_texture(factory: &mut GfxFactory, width: u32, height: u32) -> G2dTexture {
     use piston_window::texture::{CreateTexture, Format};
@@ -124,86 +829,201 @@ fn empty_texture(factory: &mut GfxFactory, width: u32, height: u32) -> G2dTextur
     ).unwrap()
 }
 
-fn update_texture(
+/// Render the seen part of the world to a new image, with a pixel for every tile
+/// (or for every block of tiles when they don't fit in `MAX_IMAGE_SIZE`)
+/// Returns the image and the part of the world it shows
+fn render_image(world: &World, settings: DrawSets) -> (RgbaImage, Viewport) {
+    let (min_x, min_y, max_x, max_y) = world.seen_tiles();
+    let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+    let block = (width.max(height) + MAX_IMAGE_SIZE - 1) / MAX_IMAGE_SIZE;
+    let (columns, rows) = ((width + block - 1) / block, (height + block - 1) / block);
+
+    let min = Vector::new(min_x as f64, min_y as f64);
+    let max = min + Vector::new(columns as f64, rows as f64) * block as f64;
+    let viewport = Viewport::new(min, max, columns, rows);
+
+    let mut imgbuf: RgbaImage = ImageBuffer::new(columns as u32, rows as u32);
+    let surface_charges = if settings.contains(DrawSets::SURFACE_CHARGE) {
+        world.surface_charges()
+    } else {
+        Chunks::new(world.width as usize, world.height as usize, 0.0)
+    };
+
+    {
+        let mut image_rows: Vec<&mut [u8]> = imgbuf.chunks_mut(columns * 4).collect();
+
+        for_each_row(&mut image_rows, |row, pixels| {
+            // Flip the y axis
+            let row = rows - 1 - row;
+
+            for column in 0..columns {
+                let point = viewport.point(column, row);
+                if !world.in_bounds(point.x.floor() as i32, point.y.floor() as i32) {
+                    continue;
+                }
+
+                let pixel = render_pixel(world, settings, &surface_charges, point);
+                pixels[column * 4..column * 4 + 4].copy_from_slice(&pixel.data);
+            }
+        });
+    }
+
+    (imgbuf, viewport)
+}
+
+/// Render the viewport of the canvas to a new image, a pixel for every sample of `field`
+fn render_view(world: &World, field: &ViewField, settings: DrawSets) -> RgbaImage {
+    let viewport = field.viewport;
+    let mut imgbuf: RgbaImage = ImageBuffer::new(viewport.columns as u32, viewport.rows as u32);
+    // The canvas has no conductors
+    let surface_charges = Chunks::new(world.width as usize, world.height as usize, 0.0);
+
+    {
+        let mut rows: Vec<&mut [u8]> = imgbuf.chunks_mut(viewport.columns * 4).collect();
+
+        for_each_row(&mut rows, |row, pixels| {
+            // Flip the y axis
+            let row = viewport.rows - 1 - row;
+
+            for column in 0..viewport.columns {
+                let point = viewport.point(column, row);
+                let (x, y) = (point.x.floor() as i32, point.y.floor() as i32);
+                if !world.in_bounds(x, y) {
+                    continue;
+                }
+
+                let (force, potential) = *field.get(column, row);
+                let (x, y) = (x as usize, y as usize);
+                let pixel =
+                    color_pixel(world, settings, &surface_charges, x, y, force, potential);
+                pixels[column * 4..column * 4 + 4].copy_from_slice(&pixel.data);
+            }
+        });
+    }
+
+    imgbuf
+}
+
+/// Render the world at `point` to a pixel
+/// `surface_charges` is the charge of every conductor tile, if it's shown
+fn render_pixel(
     world: &World,
     settings: DrawSets,
-    texture: &mut G2dTexture,
-    encoder: &mut GfxEncoder,
-) {
-    use image::Pixel;
+    surface_charges: &Chunks<f64>,
+    point: Vector,
+) -> image::Rgba<u8> {
+    let sample = world.sample(&point);
+    let (force, potential) = match sample {
+        Some(sample) => (sample.field, sample.potential),
+        None => (Vector::new(0.0, 0.0), 0.0),
+    };
 
-    // Create a new image to draw to
-    let mut imgbuf = ImageBuffer::new(world.width, world.height);
+    let (x, y) = (point.x.floor() as usize, point.y.floor() as usize);
+    color_pixel(world, settings, surface_charges, x, y, force, potential)
+}
 
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        // Flip the y axis
-        let y = world.height - 1 - y;
+/// The color of a pixel on the tile at (`x`, `y`), where the field is `force`
+fn color_pixel(
+    world: &World,
+    settings: DrawSets,
+    surface_charges: &Chunks<f64>,
+    x: usize,
+    y: usize,
+    force: Vector,
+    potential: f64,
+) -> image::Rgba<u8> {
+    use image::Pixel;
 
-        let &(ref force, potential) = world
-            .field
-            .get(&Vector::new(x as f64 + 0.5, y as f64 + 0.5));
-        let charge = world.tiles[y as usize][x as usize];
+    let charge = world.tile(x, y);
+    let conductor = world.conductor(x, y);
 
-        // Draw tiles
-        let tile_pixel = {
-            let intensity = charge.abs() as u8 * 2;
+    // Draw tiles
+    let tile_pixel = {
+        let intensity = charge.abs() as u8 * 2;
 
-            if charge > 0 {
-                // When the charge is positive draw it red
-                image::Rgba([intensity, 0, 0, 255])
-            } else if charge < 0 {
-                // When the charge is negative draw it blue
-                image::Rgba([0, 0, intensity, 255])
-            } else {
-                // When the change is neutral draw it transparent
-                image::Rgba([0, 0, 0, 0])
-            }
-        };
+        if conductor.is_some() {
+            // Conductors are drawn gray, going to red or blue with the charge of their surface
+            let charge = surface_charges.get(x, y);
+            let color = (charge.abs() / SURFACE_CHARGE_SCALE).min(1.0) * 127.0;
+            let (strong, weak) = ((128.0 + color) as u8, (128.0 - color) as u8);
 
-        // Draw potential
-        let pot_pixel = {
-            // We subtract the potential to 255
-            // To make it so that when the potential is absent
-            // The intesity is 255, drawing the white color instead of the black color
-            let intensity = 255.0 - potential.abs();
-            let intensity = if intensity < 0.0 { 0.0 } else { intensity };
-
-            if potential > 0.0 {
-                // When the potential is positive draw it reddish
-                image::Rgba([255, intensity as u8, intensity as u8, 127])
+            if charge > 0.0 {
+                image::Rgba([strong, weak, weak, 255])
             } else {
-                // When the potential is negative draw is blueish
-                image::Rgba([intensity as u8, intensity as u8, 255, 127])
+                image::Rgba([weak, weak, strong, 255])
             }
-        };
+        } else if charge > 0 {
+            // When the charge is positive draw it red
+            image::Rgba([intensity, 0, 0, 255])
+        } else if charge < 0 {
+            // When the charge is negative draw it blue
+            image::Rgba([0, 0, intensity, 255])
+        } else {
+            // When the change is neutral draw it transparent
+            image::Rgba([0, 0, 0, 0])
+        }
+    };
 
-        let field_pixel = {
-            // We subtract the field to 255
-            // To make is that when the field is absent
-            // We draw the white color instead of the black color
-            // (same as potential)
-            let force = 255.0 - force.norm();
-            let force = if force < 0.0 { 0.0 } else { force };
+    // Draw potential
+    let pot_pixel = {
+        // We subtract the potential to 255
+        // To make it so that when the potential is absent
+        // The intesity is 255, drawing the white color instead of the black color
+        let intensity = 255.0 - potential.abs();
+        let intensity = if intensity < 0.0 { 0.0 } else { intensity };
 
-            // Alpha is 255 because of the way blending works
-            image::Rgba([force as u8, force as u8, force as u8, 255])
-        };
+        if potential > 0.0 {
+            // When the potential is positive draw it reddish
+            image::Rgba([255, intensity as u8, intensity as u8, 127])
+        } else {
+            // When the potential is negative draw is blueish
+            image::Rgba([intensity as u8, intensity as u8, 255, 127])
+        }
+    };
 
-        // Blend the calculated pixels in particular order to blend them correctly
-        if settings.contains(DrawSets::FIELD) {
-            *pixel = field_pixel;
-            if settings.contains(DrawSets::POTENTIAL) {
-                pixel.blend(&pot_pixel);
-            }
-            pixel.blend(&tile_pixel);
-        } else if settings.contains(DrawSets::POTENTIAL) {
-            *pixel = pot_pixel;
-            pixel.blend(&tile_pixel);
+    // Draw the dielectrics
+    let dielectric_pixel = {
+        let permittivity = world.permittivity(x, y);
+
+        if permittivity != 1.0 {
+            // The higher the permittivity the more opaque the green
+            let alpha = 60.0 + (permittivity - 1.0).abs() * 20.0;
+            let alpha = if alpha > 200.0 { 200.0 } else { alpha };
+
+            image::Rgba([0, 160, 0, alpha as u8])
         } else {
-            *pixel = tile_pixel;
+            image::Rgba([0, 0, 0, 0])
+        }
+    };
+
+    let field_pixel = {
+        // We subtract the field to 255
+        // To make is that when the field is absent
+        // We draw the white color instead of the black color
+        // (same as potential)
+        let force = 255.0 - force.norm();
+        let force = if force < 0.0 { 0.0 } else { force };
+
+        // Alpha is 255 because of the way blending works
+        image::Rgba([force as u8, force as u8, force as u8, 255])
+    };
+
+    // Blend the calculated pixels in particular order to blend them correctly
+    let mut pixel = if settings.contains(DrawSets::FIELD) {
+        let mut pixel = field_pixel;
+        if settings.contains(DrawSets::POTENTIAL) {
+            pixel.blend(&pot_pixel);
         }
+        pixel
+    } else if settings.contains(DrawSets::POTENTIAL) {
+        pot_pixel
+    } else {
+        image::Rgba([0, 0, 0, 0])
+    };
+    if settings.contains(DrawSets::DIELECTRIC) {
+        pixel.blend(&dielectric_pixel);
     }
+    pixel.blend(&tile_pixel);
 
-    // Apply the image to the texture
-    texture.update(encoder, &imgbuf).unwrap();
+    pixel
 }
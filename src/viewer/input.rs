@@ -1,9 +1,25 @@
 use Vector;
 use piston_window::*;
 use viewer::ViewState;
+use world::boundaries::{Boundaries, Edge};
+use world::gauss::Surface;
+use world::mirrors::Mirror;
+use world::solver::Method;
+use world::trajectory::{Motion, TestParticle};
+use world::units::ChargeUnit;
+
+use std::fs::OpenOptions;
+use std::io;
 
 use super::*;
 
+/// The files the trajectories are exported to, numbered after the first one
+const TRAJECTORY_FILE: (&str, &str) = ("trajectory", "csv");
+/// The distance between the points of a drawn curve, in tiles
+const CURVE_STEP: f64 = 0.2;
+/// Shown when conductors or dielectrics meet point charges
+const MATERIALS_MESSAGE: &str = "Conductors and dielectrics need line charges (G)";
+
 /// The current state of input (buttons pressed, released, held, etc...)
 pub struct InputState {
     pub last_cursor: Vector,
@@ -125,6 +141,7 @@ pub fn handle_input(view: &mut ViewState, input: &mut InputState) {
             &Key::C => {
                 // Switch the sign of the charge
                 view.charge = -view.charge;
+                view.point_charge = -view.point_charge;
             }
             &Key::P => {
                 view.draw_settings.toggle(DrawSets::POTENTIAL);
@@ -138,6 +155,162 @@ pub fn handle_input(view: &mut ViewState, input: &mut InputState) {
                 view.draw_settings.toggle(DrawSets::FIELD);
                 view.changed = true;
             }
+            &Key::D1 => {
+                view.tool = Tool::Charge;
+            }
+            &Key::D2 => {
+                view.tool = Tool::Conductor;
+            }
+            &Key::D3 => {
+                view.tool = Tool::Dielectric;
+            }
+            &Key::D4 => {
+                view.tool = Tool::PointCharge;
+            }
+            &Key::D5 => {
+                view.tool = Tool::Plane;
+            }
+            &Key::D6 => {
+                view.tool = Tool::Circle;
+            }
+            &Key::D7 => {
+                view.tool = Tool::Particle;
+            }
+            &Key::D8 => {
+                view.tool = Tool::Gauss;
+            }
+            &Key::D9 => {
+                view.tool = Tool::Path;
+            }
+            &Key::I => {
+                view.draw_settings.toggle(DrawSets::IMAGES);
+                view.changed = true;
+            }
+            &Key::V => {
+                view.draw_settings.toggle(DrawSets::EQUIPOTENTIALS);
+                view.changed = true;
+            }
+            &Key::A => {
+                view.draw_settings.toggle(DrawSets::FORCES);
+                view.changed = true;
+            }
+            &Key::Q => {
+                view.draw_settings.toggle(DrawSets::SURFACE_CHARGE);
+                view.changed = true;
+            }
+            &Key::D => {
+                view.draw_settings.toggle(DrawSets::DIELECTRIC);
+                view.changed = true;
+            }
+            &Key::G => {
+                // Switch between point charges and line charges
+                let law = match view.world.law() {
+                    FieldLaw::PointCharges => FieldLaw::LineCharges,
+                    FieldLaw::LineCharges => FieldLaw::PointCharges,
+                };
+                if view.world.set_law(law) {
+                    view.changed = true;
+                } else {
+                    view.message = Some(MATERIALS_MESSAGE.to_string());
+                }
+            }
+            &Key::M => {
+                // Switch between charges and currents
+                let mode = match view.world.mode() {
+                    Mode::Electrostatic => Mode::Magnetostatic,
+                    Mode::Magnetostatic => Mode::Electrostatic,
+                };
+                view.world.set_mode(mode);
+                view.changed = true;
+            }
+            &Key::B => {
+                // Cycle through the conditions on the selected edge (or on all of them)
+                let boundaries = view.world.boundaries();
+                let boundaries = match view.edge {
+                    Some(edge) => boundaries.with(edge, boundaries.get(edge).next()),
+                    None => Boundaries::all(boundaries.left.next()),
+                };
+                view.world.set_boundaries(boundaries);
+                view.message = Some(edges_message(view));
+                view.changed = true;
+            }
+            &Key::J => {
+                // Select the edge whose condition changes, then all of them
+                view.edge = match view.edge {
+                    None => Some(Edge::Left),
+                    Some(Edge::Left) => Some(Edge::Right),
+                    Some(Edge::Right) => Some(Edge::Bottom),
+                    Some(Edge::Bottom) => Some(Edge::Top),
+                    Some(Edge::Top) => None,
+                };
+                view.message = Some(edges_message(view));
+            }
+            &Key::H => {
+                // Switch between the exact sum and the Barnes–Hut tree
+                let evaluator = match view.world.evaluator() {
+                    Evaluator::Direct => Evaluator::BarnesHut { theta: view.theta },
+                    Evaluator::BarnesHut { .. } => Evaluator::Direct,
+                };
+                view.world.set_evaluator(evaluator);
+                view.changed = true;
+            }
+            &Key::R => {
+                // Switch between the relaxation and the multigrid solver
+                let method = match view.world.solver_method() {
+                    Method::Relaxation => Method::Multigrid,
+                    Method::Multigrid => Method::Relaxation,
+                };
+                view.world.set_solver_method(method);
+                view.changed = true;
+            }
+            &Key::T => {
+                // Start or stop the motion of the free charges
+                view.playing = !view.playing;
+            }
+            &Key::N => {
+                // Move the free charges by a single step
+                if !view.playing && view.world.step() {
+                    view.changed = true;
+                }
+            }
+            &Key::K => {
+                // Pin or unpin the free charge under the cursor
+                let cursor = view.get_world_pos(input.cursor.x, input.cursor.y);
+                if let Some(index) = view.world.charge_at(&cursor, CHARGE_RADIUS) {
+                    let pinned = view.world.charges[index].pinned;
+                    view.world.set_pinned(index, !pinned);
+                }
+            }
+            &Key::X => {
+                // Switch between classical and relativistic test particles
+                view.trajectory_settings.motion = match view.trajectory_settings.motion {
+                    Motion::Classical => Motion::Relativistic {
+                        speed_of_light: view.world.units.speed_of_light(view.world.law()),
+                    },
+                    Motion::Relativistic { .. } => Motion::Classical,
+                };
+                view.update_trajectories();
+            }
+            &Key::E => {
+                // Save the path of the last launched particle
+                view.message = Some(match export_trajectory(view) {
+                    Ok(Some(name)) => format!("Exported the trajectory to {}", name),
+                    Ok(None) => "No trajectory to export, launch a test particle".to_string(),
+                    Err(error) => format!("Failed to export the trajectory: {}", error),
+                });
+            }
+            &Key::U => {
+                // Switch the charge of a unit between a coulomb and a proton
+                view.world.units.charge_unit = match view.world.units.charge_unit {
+                    ChargeUnit::Coulomb => ChargeUnit::Elementary,
+                    ChargeUnit::Elementary => ChargeUnit::Coulomb,
+                };
+            }
+            &Key::O => {
+                // Switch between a world of the inputted size and the unbounded canvas,
+                // the render loop replaces the world
+                view.unbounded = !view.unbounded;
+            }
             &Key::Space => {
                 view.offset.x = -(view.world.width as f64 / 2.0);
                 view.offset.y = view.world.height as f64 / 2.0;
@@ -151,6 +324,49 @@ pub fn handle_input(view: &mut ViewState, input: &mut InputState) {
     handle_edit(view, input);
 }
 
+/// The conditions on the edges, with the selected one
+fn edges_message(view: &ViewState) -> String {
+    let boundaries = view.world.boundaries();
+    let edges = format!(
+        "left {:?}, right {:?}, bottom {:?}, top {:?}",
+        boundaries.left, boundaries.right, boundaries.bottom, boundaries.top
+    );
+    match view.edge {
+        Some(edge) => format!("Edges: {} (changing {:?})", edges, edge),
+        None => format!("Edges: {} (changing all)", edges),
+    }
+}
+
+/// Write the path of the last launched particle to a new file, never replacing an older one
+/// Returns the name of the file, or `None` without any particle
+fn export_trajectory(view: &ViewState) -> io::Result<Option<String>> {
+    let trajectory = match view.trajectories.last() {
+        Some(trajectory) => trajectory,
+        None => return Ok(None),
+    };
+    let law = match view.world.mode() {
+        Mode::Electrostatic => Some(view.world.law()),
+        Mode::Magnetostatic => None,
+    };
+
+    let (stem, extension) = TRAJECTORY_FILE;
+    let mut number = 0;
+    loop {
+        let name = match number {
+            0 => format!("{}.{}", stem, extension),
+            _ => format!("{}-{}.{}", stem, number, extension),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&name) {
+            Ok(file) => {
+                trajectory.write_csv(file, &view.world.units, law)?;
+                return Ok(Some(name));
+            }
+            Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => number += 1,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
 fn handle_move(view: &mut ViewState, input: &InputState) {
     if let Some(MouseButton::Right) = input.held_mouse {
         if input.held_keys.contains(&Key::LShift) {
@@ -166,21 +382,265 @@ fn handle_move(view: &mut ViewState, input: &InputState) {
 fn handle_edit(view: &mut ViewState, input: &InputState) {
     let cursor = view.get_world_pos(input.cursor.x, input.cursor.y);
 
+    match view.tool {
+        Tool::PointCharge => {
+            handle_point_charges(view, input, cursor);
+            return;
+        }
+        Tool::Plane | Tool::Circle => {
+            handle_mirrors(view, input, cursor);
+            return;
+        }
+        Tool::Particle => {
+            handle_particles(view, input, cursor);
+            return;
+        }
+        Tool::Gauss => {
+            handle_gauss(view, input, cursor);
+            return;
+        }
+        Tool::Path => {
+            handle_path(view, input, cursor);
+            return;
+        }
+        _ => {}
+    }
+
     if view.world.in_bounds(cursor.x as i32, cursor.y as i32) {
-        match input.held_mouse {
-            Some(MouseButton::Left) => {
+        let (x, y) = (cursor.x as usize, cursor.y as usize);
+
+        match (view.tool, input.held_mouse) {
+            (Tool::Charge, Some(MouseButton::Left)) => {
                 // Set the charge to the current editing charge
-                view.changed =
-                    view.world
-                        .update_tile(view.charge, cursor.x as usize, cursor.y as usize);
+                view.changed = view.world.update_tile(view.charge, x, y);
             }
-            Some(MouseButton::Right) => {
+            (Tool::Charge, Some(MouseButton::Right)) => {
                 // Erasing just sets the charge to neutral (0)
-                view.changed = view.world
-                    .update_tile(0, cursor.x as usize, cursor.y as usize);
+                view.changed = view.world.update_tile(0, x, y);
+            }
+            (Tool::Conductor, Some(MouseButton::Left)) => {
+                if view.world.law() == FieldLaw::PointCharges {
+                    view.message = Some(MATERIALS_MESSAGE.to_string());
+                }
+                // The voltage is inputted in volts
+                let volts = view.world.units.volts(view.world.law());
+                view.changed = view.world.update_conductor(Some(view.voltage / volts), x, y);
+            }
+            (Tool::Conductor, Some(MouseButton::Right)) => {
+                view.changed = view.world.update_conductor(None, x, y);
+            }
+            (Tool::Dielectric, Some(MouseButton::Left)) => {
+                if view.world.law() == FieldLaw::PointCharges {
+                    view.message = Some(MATERIALS_MESSAGE.to_string());
+                }
+                view.changed = view.world.update_permittivity(view.permittivity, x, y);
+            }
+            (Tool::Dielectric, Some(MouseButton::Right)) => {
+                // Erasing a dielectric leaves the vacuum
+                view.changed = view.world.update_permittivity(1.0, x, y);
             }
 
             _ => {}
         }
     }
 }
+
+fn handle_point_charges(view: &mut ViewState, input: &InputState, cursor: Vector) {
+    if input.released_mouse.is_some() {
+        view.dragging = None;
+    }
+
+    if !view.world.in_bounds(cursor.x as i32, cursor.y as i32) {
+        return;
+    }
+
+    match input.pressed_mouse {
+        Some(MouseButton::Left) => {
+            // Pick the charge under the cursor or place a new one
+            let index = match view.world.charge_at(&cursor, CHARGE_RADIUS) {
+                Some(index) => index,
+                None => {
+                    let mut charge = Charge::new(cursor, view.point_charge);
+                    charge.mass = view.mass;
+
+                    view.changed = true;
+                    view.world.add_charge(charge)
+                }
+            };
+            view.dragging = Some(index);
+        }
+        Some(MouseButton::Right) => {
+            if let Some(index) = view.world.charge_at(&cursor, CHARGE_RADIUS) {
+                view.world.remove_charge(index);
+                view.changed = true;
+            }
+        }
+
+        _ => {}
+    }
+
+    if let (Some(MouseButton::Left), Some(index)) = (input.held_mouse, view.dragging) {
+        if view.world.move_charge(index, cursor) {
+            view.changed = true;
+        }
+    }
+}
+
+fn handle_mirrors(view: &mut ViewState, input: &InputState, cursor: Vector) {
+    match input.pressed_mouse {
+        Some(MouseButton::Left) => {
+            if view.world.in_bounds(cursor.x as i32, cursor.y as i32) {
+                view.drag_start = Some(cursor);
+            }
+        }
+        Some(MouseButton::Right) => {
+            if let Some(index) = view.world.mirror_at(&cursor, 0.5) {
+                view.world.remove_mirror(index);
+                view.changed = true;
+            }
+        }
+
+        _ => {}
+    }
+
+    // The mirror is placed when the drag ends
+    if let (Some(MouseButton::Left), Some(start)) = (input.released_mouse, view.drag_start) {
+        view.drag_start = None;
+
+        let delta = cursor - start;
+        if delta.norm() < 0.5 {
+            return;
+        }
+
+        let mirror = match view.tool {
+            // The plane goes along the drag, the free side is on its left
+            Tool::Plane => Mirror::plane(start, Vector::new(-delta.y, delta.x)),
+            // The circle is centered on the start of the drag
+            _ => Mirror::circle(start, delta.norm()),
+        };
+        view.world.add_mirror(mirror);
+        view.changed = true;
+    }
+}
+
+fn handle_particles(view: &mut ViewState, input: &InputState, cursor: Vector) {
+    match input.pressed_mouse {
+        Some(MouseButton::Left) => {
+            if view.world.in_bounds(cursor.x as i32, cursor.y as i32) {
+                view.drag_start = Some(cursor);
+            }
+        }
+        Some(MouseButton::Right) => {
+            view.trajectories.clear();
+        }
+
+        _ => {}
+    }
+
+    // The particle is launched from the start of the drag, with the drag as velocity
+    if let (Some(MouseButton::Left), Some(start)) = (input.released_mouse, view.drag_start) {
+        view.drag_start = None;
+
+        let particle = TestParticle::new(start, cursor - start, view.point_charge, view.mass);
+        view.update_speed_of_light();
+        let trajectory = view.world.trace_particle(&particle, &view.trajectory_settings);
+        view.trajectories.push(trajectory);
+    }
+}
+
+fn handle_gauss(view: &mut ViewState, input: &InputState, cursor: Vector) {
+    match input.pressed_mouse {
+        Some(MouseButton::Left) => {
+            if view.world.in_bounds(cursor.x as i32, cursor.y as i32) {
+                view.drag_start = Some(cursor);
+                view.drawn_curve = vec![cursor];
+            }
+        }
+        Some(MouseButton::Right) => {
+            view.surface = None;
+            view.flux = None;
+        }
+
+        _ => {}
+    }
+
+    follow_drag(view, input, cursor);
+
+    // The surface is measured when the drag ends
+    if let (Some(MouseButton::Left), Some(start)) = (input.released_mouse, view.drag_start) {
+        let surface = drawn_surface(view, input);
+        view.drag_start = None;
+        view.drawn_curve.clear();
+
+        if (cursor - start).norm() >= 0.5 {
+            view.surface = surface;
+            view.update_flux();
+        }
+    }
+}
+
+/// The surface being drawn with the Gauss tool: a curve following the drag,
+/// a circle from its center while holding `Shift` or a rectangle while holding `Ctrl`
+pub fn drawn_surface(view: &ViewState, input: &InputState) -> Option<Surface> {
+    if view.tool != Tool::Gauss {
+        return None;
+    }
+
+    let start = view.drag_start?;
+    let cursor = view.get_world_pos(input.cursor.x, input.cursor.y);
+
+    let surface = if input.held_keys.contains(&Key::LShift) {
+        Surface::Circle {
+            center: start,
+            radius: (cursor - start).norm(),
+        }
+    } else if input.held_keys.contains(&Key::LCtrl) {
+        Surface::rectangle(start, cursor)
+    } else {
+        let mut points = view.drawn_curve.clone();
+        points.push(cursor);
+        Surface::Polygon(points)
+    };
+
+    Some(surface)
+}
+
+fn handle_path(view: &mut ViewState, input: &InputState, cursor: Vector) {
+    match input.pressed_mouse {
+        Some(MouseButton::Left) => {
+            if view.world.in_bounds(cursor.x as i32, cursor.y as i32) {
+                view.drag_start = Some(cursor);
+                view.drawn_curve = vec![cursor];
+            }
+        }
+        Some(MouseButton::Right) => {
+            view.path.clear();
+            view.line_integral = None;
+        }
+
+        _ => {}
+    }
+
+    follow_drag(view, input, cursor);
+
+    // The path is measured when the drag ends
+    if let (Some(MouseButton::Left), Some(_)) = (input.released_mouse, view.drag_start) {
+        view.drag_start = None;
+        view.drawn_curve.push(cursor);
+        view.path = view.drawn_curve.split_off(0);
+        view.update_line_integral();
+    }
+}
+
+/// Add the cursor to the curve being drawn when it has moved enough
+fn follow_drag(view: &mut ViewState, input: &InputState, cursor: Vector) {
+    if let (Some(MouseButton::Left), Some(_)) = (input.held_mouse, view.drag_start) {
+        let moved = match view.drawn_curve.last() {
+            Some(last) => (cursor - last).norm() > CURVE_STEP,
+            None => true,
+        };
+        if moved {
+            view.drawn_curve.push(cursor);
+        }
+    }
+}
@@ -60,6 +60,29 @@ impl InputBox {
         }
     }
 
+    // Get the input box value parsed to type T, only when `valid` accepts it
+    // Otherwise the input box shows the previous value again, and false is returned
+    pub fn input_checked<T, F>(&mut self, value: &mut T, valid: F) -> bool
+    where
+        T: FromStr + ToString,
+        F: Fn(&T) -> bool,
+    {
+        if self.active {
+            return true;
+        }
+
+        match self.input.parse() {
+            Ok(ref new_value) if !valid(new_value) => {}
+            Ok(new_value) => {
+                *value = new_value;
+                return true;
+            }
+            Err(_) => {}
+        }
+        self.input = value.to_string();
+        false
+    }
+
     // Render and process the input events
     pub fn update(&mut self, input: &mut InputState, c: &Context, g: &mut G2d) {
         let full_text = format!("{}: {}", self.description, self.input);
@@ -0,0 +1,49 @@
+use piston_window::*;
+
+/// A GUI text label, showing one line of text
+pub struct Label {
+    // The text shown
+    pub text: String,
+
+    // Offset from the screen upper left corner
+    offset: (f64, f64),
+
+    glyphs: Glyphs,
+}
+
+impl Label {
+    pub fn new(factory: GfxFactory, offset: (f64, f64)) -> Label {
+        let font = include_bytes!("../../assets/FiraSans-Regular.ttf");
+        let glyphs = Glyphs::from_bytes(font, factory, TextureSettings::new()).unwrap();
+
+        Label {
+            text: String::new(),
+            offset,
+            glyphs,
+        }
+    }
+
+    // Render the label, nothing is drawn when the text is empty
+    pub fn draw(&mut self, c: &Context, g: &mut G2d) {
+        if self.text.is_empty() {
+            return;
+        }
+
+        let text = self.text.clone();
+        let offset = self.offset;
+        self.draw_at(&text, offset, c, g);
+    }
+
+    // Render some other text with the font of the label, at `position` on the screen
+    pub fn draw_at(&mut self, text: &str, position: (f64, f64), c: &Context, g: &mut G2d) {
+        text::Text::new_color([0.0, 0.0, 0.0, 1.0], 16)
+            .draw(
+                text,
+                &mut self.glyphs,
+                &c.draw_state,
+                c.transform.trans(position.0, position.1),
+                g,
+            )
+            .unwrap();
+    }
+}
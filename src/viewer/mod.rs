@@ -1,8 +1,14 @@
 pub mod drawing;
 pub mod input;
 pub mod inputbox;
+pub mod label;
 
 use Vector;
+use world::boundaries::Edge;
+use world::gauss::{FluxReport, Surface};
+use world::path::LineIntegral;
+use world::trajectory::{Motion, Trajectory, TrajectorySettings};
+use world::viewport::Viewport;
 use world::*;
 
 bitflags! {
@@ -10,18 +16,79 @@ bitflags! {
         const POTENTIAL = 0b00000001;
         const FIELD = 0b00000010;
         const FIELD_LINES = 0b00000100;
+        const DIELECTRIC = 0b00001000;
+        const IMAGES = 0b00010000;
+        const EQUIPOTENTIALS = 0b00100000;
+        const FORCES = 0b01000000;
+        const SURFACE_CHARGE = 0b10000000;
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// What is placed on the world when editing
+pub enum Tool {
+    Charge,
+    Conductor,
+    Dielectric,
+    PointCharge,
+    // Grounded planes and circles
+    Plane,
+    Circle,
+    // Test particles launched through the field
+    Particle,
+    // Closed surfaces measuring the flux (Gauss's law)
+    Gauss,
+    // Paths measuring the work of the field and the potential difference
+    Path,
+}
+
 /// The view on the world used for rendering
 pub struct ViewState {
     pub world: World,
+    // Should the world be an unbounded canvas? (see `World::new_unbounded`)
+    pub unbounded: bool,
     // The field has changed and so the view must be updated
     pub changed: bool,
+    // The editing tool
+    pub tool: Tool,
     // The editing charge
     pub charge: i8,
+    // The magnitude of the placed free charges
+    pub point_charge: f64,
+    // The mass of the placed free charges
+    pub mass: f64,
+    // The free charge being dragged
+    pub dragging: Option<usize>,
+    // Where the drag placing a plane or a circle (or launching a particle) started
+    pub drag_start: Option<Vector>,
+    // The paths of the launched test particles
+    pub trajectories: Vec<Trajectory>,
+    pub trajectory_settings: TrajectorySettings,
+    // The points of the curve or the path being drawn
+    pub drawn_curve: Vec<Vector>,
+    // The surface measuring the flux and its last measure
+    pub surface: Option<Surface>,
+    pub flux: Option<FluxReport>,
+    // The path measuring the work of the field and its last measure
+    pub path: Vec<Vector>,
+    pub line_integral: Option<LineIntegral>,
+    // The potential of the placed conductors, in V
+    pub voltage: f64,
+    // The relative permittivity of the painted dielectrics
+    pub permittivity: f64,
+    // The accuracy of the Barnes–Hut evaluator
+    pub theta: f64,
+    // Are the free charges moving?
+    pub playing: bool,
+    // The potential difference between the drawn equipotentials
+    pub contour_spacing: f64,
+    // The edge whose condition is changed, all of them when `None`
+    pub edge: Option<Edge>,
+    // A message for the user, shown in the status until the next calculation
+    pub message: Option<String>,
 
-    // The draw setting (show potential, show field, show lines)
+    // The draw setting (show potential, show field, show lines, show dielectrics, show images,
+    // show equipotentials, show forces, show the charge of the conductors)
     pub draw_settings: DrawSets,
 
     // The scale of the visualization
@@ -36,11 +103,36 @@ pub struct ViewState {
 
 impl ViewState {
     pub fn new(world: World) -> ViewState {
+        let unbounded = world.is_unbounded();
         let mut view = ViewState {
             world,
+            unbounded,
             changed: true,
+            tool: Tool::Charge,
             charge: 127,
-            draw_settings: DrawSets::POTENTIAL | DrawSets::FIELD | DrawSets::FIELD_LINES,
+            point_charge: 100.0,
+            mass: 1.0,
+            dragging: None,
+            drag_start: None,
+            trajectories: Vec::new(),
+            trajectory_settings: TrajectorySettings::new(),
+            drawn_curve: Vec::new(),
+            surface: None,
+            flux: None,
+            path: Vec::new(),
+            line_integral: None,
+            voltage: 100.0,
+            permittivity: 4.0,
+            theta: 0.5,
+            playing: false,
+            contour_spacing: 10.0,
+            edge: None,
+            message: None,
+            draw_settings: DrawSets::POTENTIAL
+                | DrawSets::FIELD
+                | DrawSets::FIELD_LINES
+                | DrawSets::DIELECTRIC
+                | DrawSets::IMAGES,
             scale: 10.0,
             width: 1,
             height: 1,
@@ -67,6 +159,15 @@ impl ViewState {
         )
     }
 
+    /// The part of the world seen on the screen, sampled on every pixel
+    /// (a minimized window still has a pixel)
+    pub fn viewport(&self) -> Viewport {
+        let (width, height) = (self.width.max(1), self.height.max(1));
+        let min = self.get_world_pos(0.0, height as f64);
+        let max = self.get_world_pos(width as f64, 0.0);
+        Viewport::new(min, max, width as usize, height as usize)
+    }
+
     /// Are the coordinates inside the screen?
     pub fn in_screen(&self, x: f64, y: f64) -> bool {
         if x > self.width as f64 || y > self.height as f64 || x < 0.0 || y < 0.0 {
@@ -76,6 +177,44 @@ impl ViewState {
         }
     }
 
+    /// Convert the speed of light of the relativistic test particles
+    /// to the current units of the world (the cell size and the law change it)
+    pub fn update_speed_of_light(&mut self) {
+        let law = self.world.law();
+        if let Motion::Relativistic {
+            ref mut speed_of_light,
+        } = self.trajectory_settings.motion
+        {
+            *speed_of_light = self.world.units.speed_of_light(law);
+        }
+    }
+
+    /// Trace again the launched test particles, through the current field
+    pub fn update_trajectories(&mut self) {
+        self.update_speed_of_light();
+        let settings = self.trajectory_settings;
+        for trajectory in &mut self.trajectories {
+            *trajectory = self.world.trace_particle(&trajectory.particle, &settings);
+        }
+    }
+
+    /// Measure again the flux through the surface, in the current field
+    pub fn update_flux(&mut self) {
+        self.flux = match self.surface {
+            Some(ref surface) => Some(self.world.flux(surface)),
+            None => None,
+        };
+    }
+
+    /// Measure again the work along the path, in the current field
+    pub fn update_line_integral(&mut self) {
+        self.line_integral = if self.path.len() > 1 {
+            Some(self.world.line_integral(&self.path))
+        } else {
+            None
+        };
+    }
+
     /// Centers the view on the world
     pub fn center_view(&mut self) {
         self.offset.x = -(self.world.width as f64 / 2.0);
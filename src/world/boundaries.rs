@@ -0,0 +1,211 @@
+use Vector;
+
+/// How many times a charge is reflected (or repeated) on each axis
+/// The images further away are ignored, so a periodic world is only repeated
+/// this many times on each side (the sum over all the copies of a charge diverges)
+/// Between grounded edges the furthest images are weakened to keep the images neutral
+const IMAGE_DEPTH: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The condition on an edge of the world
+pub enum Boundary {
+    /// The world continues in empty space
+    Open,
+    /// The edge is a conductor at zero potential
+    Grounded,
+    /// No field crosses the edge
+    Insulating,
+    /// The world repeats past the edge, `IMAGE_DEPTH` times
+    /// (only when the opposite edge is periodic too, otherwise it is open)
+    Periodic,
+}
+
+impl Boundary {
+    /// The next condition, to cycle through them
+    pub fn next(self) -> Boundary {
+        match self {
+            Boundary::Open => Boundary::Grounded,
+            Boundary::Grounded => Boundary::Insulating,
+            Boundary::Insulating => Boundary::Periodic,
+            Boundary::Periodic => Boundary::Open,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// An edge of the world
+pub enum Edge {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The conditions on the four edges of the world
+pub struct Boundaries {
+    pub left: Boundary,
+    pub right: Boundary,
+    pub bottom: Boundary,
+    pub top: Boundary,
+}
+
+impl Boundaries {
+    /// The same condition on every edge
+    pub fn all(boundary: Boundary) -> Boundaries {
+        Boundaries {
+            left: boundary,
+            right: boundary,
+            bottom: boundary,
+            top: boundary,
+        }
+    }
+
+    /// The condition on `edge`
+    pub fn get(&self, edge: Edge) -> Boundary {
+        match edge {
+            Edge::Left => self.left,
+            Edge::Right => self.right,
+            Edge::Bottom => self.bottom,
+            Edge::Top => self.top,
+        }
+    }
+
+    /// The same conditions with `boundary` on `edge`
+    pub fn with(mut self, edge: Edge, boundary: Boundary) -> Boundaries {
+        match edge {
+            Edge::Left => self.left = boundary,
+            Edge::Right => self.right = boundary,
+            Edge::Bottom => self.bottom = boundary,
+            Edge::Top => self.top = boundary,
+        }
+        self
+    }
+
+    pub fn periodic_x(&self) -> bool {
+        self.left == Boundary::Periodic && self.right == Boundary::Periodic
+    }
+
+    pub fn periodic_y(&self) -> bool {
+        self.bottom == Boundary::Periodic && self.top == Boundary::Periodic
+    }
+
+    /// Are all the edges open?
+    pub fn is_open(&self) -> bool {
+        self.left == Boundary::Open
+            && self.right == Boundary::Open
+            && self.bottom == Boundary::Open
+            && self.top == Boundary::Open
+    }
+
+    /// The charges that, added to `charge` at `position`, satisfy the conditions on the edges
+    /// of a world `width` wide and `height` high (the charge itself is the first one)
+    pub fn images(
+        &self,
+        position: &Vector,
+        charge: f64,
+        width: f64,
+        height: f64,
+    ) -> Vec<(Vector, f64)> {
+        let x_images = axis_images(position.x, width, self.left, self.right);
+        let y_images = axis_images(position.y, height, self.bottom, self.top);
+
+        let mut images = Vec::with_capacity(x_images.len() * y_images.len());
+        for &(y, y_sign) in &y_images {
+            for &(x, x_sign) in &x_images {
+                images.push((Vector::new(x, y), charge * x_sign * y_sign));
+            }
+        }
+
+        images
+    }
+}
+
+/// The images (position, sign) of `position` along an axis `length` long
+fn axis_images(position: f64, length: f64, low: Boundary, high: Boundary) -> Vec<(f64, f64)> {
+    let mut images = vec![(position, 1.0)];
+
+    if low == Boundary::Periodic && high == Boundary::Periodic {
+        for i in 1..IMAGE_DEPTH + 1 {
+            let shift = i as f64 * length;
+            images.push((position - shift, 1.0));
+            images.push((position + shift, 1.0));
+        }
+        return images;
+    }
+
+    // Reflect the charge on the two edges in turn,
+    // once starting from the low edge and once from the high one
+    let mut ends = Vec::new();
+    for &start_low in &[true, false] {
+        let (mut image, mut sign) = (position, 1.0);
+        let mut on_low = start_low;
+        let first = images.len();
+
+        for _ in 0..IMAGE_DEPTH {
+            let boundary = if on_low { low } else { high };
+            sign *= match boundary {
+                Boundary::Grounded => -1.0,
+                Boundary::Insulating => 1.0,
+                // Nothing is reflected by open edges
+                Boundary::Open | Boundary::Periodic => break,
+            };
+
+            image = if on_low { -image } else { 2.0 * length - image };
+            images.push((image, sign));
+            on_low = !on_low;
+        }
+        // The furthest image of the chain
+        if images.len() > first {
+            ends.push(images.len() - 1);
+        }
+    }
+
+    // A grounded edge takes all the charge, so the charge and its images add up to nothing
+    // (the images left out would make up for the rest, above all for line charges)
+    if low == Boundary::Grounded || high == Boundary::Grounded {
+        let total: f64 = images.iter().map(|&(_, sign)| sign).sum();
+        for &end in &ends {
+            images[end].1 -= total / ends.len() as f64;
+        }
+    }
+
+    images
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The potential of line charges (-q ln r) and of point charges (q / r) at `position`
+    fn potentials(images: &[(Vector, f64)], position: &Vector) -> (f64, f64) {
+        images
+            .iter()
+            .fold((0.0, 0.0), |(line, point), &(image, charge)| {
+                let distance = (position - image).norm();
+                (line - charge * distance.ln(), point + charge / distance)
+            })
+    }
+
+    #[test]
+    fn grounded_edges_are_at_zero() {
+        let (width, height) = (20.0, 20.0);
+        let charge = Vector::new(10.0, 10.0);
+        for &other in &[Boundary::Grounded, Boundary::Insulating, Boundary::Open] {
+            let boundaries = Boundaries::all(Boundary::Grounded).with(Edge::Right, other);
+            let images = boundaries.images(&charge, 1.0, width, height);
+            let total: f64 = images.iter().map(|&(_, charge)| charge).sum();
+            assert!(total.abs() < 1e-12);
+
+            // Compared to the potential of the charge one tile away
+            let near = potentials(&images, &Vector::new(11.0, 10.0));
+            for i in 0..21 {
+                for &position in &[Vector::new(0.0, i as f64), Vector::new(i as f64, 0.0)] {
+                    let (line, point) = potentials(&images, &position);
+                    assert!((line / near.0).abs() < 0.02, "{:?} {:?}", other, position);
+                    assert!((point / near.1).abs() < 0.002, "{:?} {:?}", other, position);
+                }
+            }
+        }
+    }
+}
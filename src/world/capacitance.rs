@@ -0,0 +1,62 @@
+use world::chunks::Chunks;
+
+use std::collections::{BTreeSet, HashSet};
+
+#[derive(Debug, Clone, PartialEq)]
+/// A conductor made of connected tiles held at the same potential
+pub struct ConductorBody {
+    pub tiles: Vec<(usize, usize)>,
+    pub potential: f64,
+    // The charge induced on the surface of the conductor
+    pub charge: f64,
+}
+
+/// The groups of conductor `tiles` touching on a side and held at the same potential,
+/// in the order of their first tile row by row
+pub fn conductor_groups(
+    tiles: &BTreeSet<(usize, usize)>,
+    conductors: &Chunks<Option<f64>>,
+) -> Vec<Vec<(usize, usize)>> {
+    let (width, height) = (conductors.width(), conductors.height());
+    let mut starts: Vec<(usize, usize)> = tiles.iter().cloned().collect();
+    starts.sort_by_key(|&(x, y)| (y, x));
+
+    let mut grouped = HashSet::new();
+    let mut groups = Vec::new();
+    for (x, y) in starts {
+        let potential = match conductors.get(x, y) {
+            Some(potential) if !grouped.contains(&(x, y)) => potential,
+            _ => continue,
+        };
+
+        // Flood the tiles of the conductor
+        let mut group = Vec::new();
+        let mut stack = vec![(x, y)];
+        grouped.insert((x, y));
+        while let Some((x, y)) = stack.pop() {
+            group.push((x, y));
+
+            let neighbors = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for &(nx, ny) in neighbors.iter() {
+                if nx < width
+                    && ny < height
+                    && !grouped.contains(&(nx, ny))
+                    && conductors.get(nx, ny) == Some(potential)
+                {
+                    grouped.insert((nx, ny));
+                    stack.push((nx, ny));
+                }
+            }
+        }
+
+        group.sort();
+        groups.push(group);
+    }
+
+    groups
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+/// The side of a chunk, in tiles
+pub const CHUNK_SIZE: usize = 32;
+
+#[inline]
+/// The chunk holding the tile (`x`, `y`)
+pub fn chunk_of(x: usize, y: usize) -> (usize, usize) {
+    (x / CHUNK_SIZE, y / CHUNK_SIZE)
+}
+
+#[derive(Debug, Clone)]
+struct Chunk<T> {
+    cells: Vec<T>,
+    // The number of cells which are not empty
+    filled: usize,
+}
+
+#[derive(Debug, Clone)]
+/// A grid of tiles stored in square chunks of `CHUNK_SIZE` tiles
+/// Only the chunks holding a tile different from `empty` are allocated,
+/// so a big world with few things on it takes little memory
+pub struct Chunks<T> {
+    width: usize,
+    height: usize,
+    // The value of the tiles out of the allocated chunks
+    empty: T,
+    chunks: HashMap<(usize, usize), Chunk<T>>,
+}
+
+impl<T: Copy + PartialEq> Chunks<T> {
+    pub fn new(width: usize, height: usize, empty: T) -> Chunks<T> {
+        Chunks {
+            width,
+            height,
+            empty,
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> T {
+        match self.chunks.get(&chunk_of(x, y)) {
+            Some(chunk) => chunk.cells[(y % CHUNK_SIZE) * CHUNK_SIZE + x % CHUNK_SIZE],
+            None => self.empty,
+        }
+    }
+
+    /// Change the tile at (`x`, `y`), returning its old value
+    /// The chunk is allocated with its first tile and freed with its last one
+    pub fn set(&mut self, x: usize, y: usize, value: T) -> T {
+        let key = chunk_of(x, y);
+        let index = (y % CHUNK_SIZE) * CHUNK_SIZE + x % CHUNK_SIZE;
+        let empty = self.empty;
+        if value == empty && !self.chunks.contains_key(&key) {
+            return empty;
+        }
+
+        let (old, emptied) = {
+            let chunk = self.chunks.entry(key).or_insert_with(|| Chunk {
+                cells: vec![empty; CHUNK_SIZE * CHUNK_SIZE],
+                filled: 0,
+            });
+            let old = chunk.cells[index];
+            chunk.cells[index] = value;
+
+            if old == empty && value != empty {
+                chunk.filled += 1;
+            } else if old != empty && value == empty {
+                chunk.filled -= 1;
+            }
+            (old, chunk.filled == 0)
+        };
+
+        if emptied {
+            self.chunks.remove(&key);
+        }
+        old
+    }
+
+    /// Are all the tiles empty?
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// The allocated chunks, sorted
+    pub fn chunks(&self) -> Vec<(usize, usize)> {
+        let mut chunks: Vec<(usize, usize)> = self.chunks.keys().cloned().collect();
+        chunks.sort();
+        chunks
+    }
+
+    /// Empty every tile
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+}
@@ -0,0 +1,304 @@
+use Vector;
+
+use std::collections::HashMap;
+
+/// The maximum number of levels of evenly spaced contours
+pub const MAX_LEVELS: usize = 100;
+
+/// The polylines where `values` crosses `level` (marching squares)
+/// `values` is a grid `width` wide stored row by row, with the samples on integer positions,
+/// the points of the polylines are in the same coordinates
+/// Closed curves end with their first point
+pub fn contour(values: &[f64], width: usize, height: usize, level: f64) -> Vec<Vec<Vector>> {
+    if width < 2 || height < 2 {
+        return Vec::new();
+    }
+
+    let value = |x: usize, y: usize| values[y * width + x];
+    let above = |x: usize, y: usize| value(x, y) >= level;
+
+    // The edges of the grid are numbered by their lower corner,
+    // even for the horizontal edges and odd for the vertical ones
+    let horizontal = |x: usize, y: usize| 2 * (y * width + x);
+    let vertical = |x: usize, y: usize| 2 * (y * width + x) + 1;
+
+    // The edges joined by a piece of curve in each cell
+    let mut links: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut link = |a: usize, b: usize| {
+        links.entry(a).or_insert_with(Vec::new).push(b);
+        links.entry(b).or_insert_with(Vec::new).push(a);
+    };
+
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+            if corners.iter().any(|&(x, y)| !value(x, y).is_finite()) {
+                continue;
+            }
+
+            // The sides of the cell (bottom, right, top, left)
+            // and whether the curve crosses them
+            let sides = [
+                (horizontal(x, y), above(x, y) != above(x + 1, y)),
+                (vertical(x + 1, y), above(x + 1, y) != above(x + 1, y + 1)),
+                (horizontal(x, y + 1), above(x, y + 1) != above(x + 1, y + 1)),
+                (vertical(x, y), above(x, y) != above(x, y + 1)),
+            ];
+            let crossed: Vec<usize> = sides
+                .iter()
+                .filter(|&&(_, crossed)| crossed)
+                .map(|&(edge, _)| edge)
+                .collect();
+
+            if crossed.len() == 2 {
+                link(crossed[0], crossed[1]);
+            } else if crossed.len() == 4 {
+                // A saddle, the curves go around the corners on the other side of the center
+                let center = corners.iter().map(|&(x, y)| value(x, y)).sum::<f64>() / 4.0;
+                for (corner, &(cx, cy)) in corners.iter().enumerate() {
+                    if above(cx, cy) != (center >= level) {
+                        // The two sides touching the corner
+                        link(sides[(corner + 3) % 4].0, sides[corner].0);
+                    }
+                }
+            }
+        }
+    }
+
+    // The point where the curve crosses an edge
+    let point = |edge: usize| {
+        let (x, y) = ((edge / 2) % width, (edge / 2) / width);
+        let (nx, ny) = if edge % 2 == 0 { (x + 1, y) } else { (x, y + 1) };
+        let (a, b) = (value(x, y), value(nx, ny));
+        let t = if a != b { (level - a) / (b - a) } else { 0.5 };
+
+        Vector::new(
+            x as f64 + (nx - x) as f64 * t,
+            y as f64 + (ny - y) as f64 * t,
+        )
+    };
+
+    // Follow the links, starting with the open curves so they are traced whole
+    let mut starts: Vec<usize> = links
+        .iter()
+        .filter(|&(_, neighbors)| neighbors.len() == 1)
+        .map(|(&edge, _)| edge)
+        .collect();
+    starts.sort();
+    let mut others: Vec<usize> = links.keys().cloned().collect();
+    others.sort();
+    starts.extend(others);
+
+    let mut polylines = Vec::new();
+    for start in starts {
+        if !links.contains_key(&start) {
+            continue;
+        }
+
+        let mut polyline = vec![point(start)];
+        let mut current = start;
+        loop {
+            // Take the next edge, removing the link both ways
+            let next = match links.get_mut(&current).and_then(|neighbors| neighbors.pop()) {
+                Some(next) => next,
+                None => break,
+            };
+            if let Some(neighbors) = links.get_mut(&next) {
+                if let Some(i) = neighbors.iter().position(|&edge| edge == current) {
+                    neighbors.remove(i);
+                }
+            }
+            if links[&current].is_empty() {
+                links.remove(&current);
+            }
+
+            polyline.push(point(next));
+            current = next;
+        }
+        links.remove(&current);
+
+        if polyline.len() > 1 {
+            polylines.push(polyline);
+        }
+    }
+
+    polylines
+}
+
+/// The multiples of `spacing` between `min` and `max`
+/// When there are more than `MAX_LEVELS` of them, the ones closest to zero are kept
+pub fn even_levels(min: f64, max: f64, spacing: f64) -> Vec<f64> {
+    if spacing <= 0.0 || !min.is_finite() || !max.is_finite() {
+        return Vec::new();
+    }
+
+    let first = (min / spacing).ceil() as i64;
+    let last = (max / spacing).floor() as i64;
+    let half = MAX_LEVELS as i64 / 2;
+    let center = 0.max(first).min(last);
+    let low = first.max(center - half);
+    let high = last.min(low + MAX_LEVELS as i64 - 1);
+    // Past the end of the range on one side, the levels are taken on the other
+    let low = first.max(high - MAX_LEVELS as i64 + 1);
+
+    (low..high + 1).map(|i| i as f64 * spacing).collect()
+}
+
+/// Join the polylines sharing an end, like the pieces of the curves traced on neighboring
+/// parts of a grid (the points on the shared sides are the same)
+pub fn join_polylines(polylines: Vec<Vec<Vector>>) -> Vec<Vec<Vector>> {
+    let key = |point: &Vector| (point.x.to_bits(), point.y.to_bits());
+
+    // The polylines starting or ending at every point
+    let mut ends: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for (i, polyline) in polylines.iter().enumerate() {
+        ends.entry(key(&polyline[0])).or_insert_with(Vec::new).push(i);
+        ends.entry(key(&polyline[polyline.len() - 1]))
+            .or_insert_with(Vec::new)
+            .push(i);
+    }
+
+    let mut used = vec![false; polylines.len()];
+    let mut joined = Vec::new();
+    for start in 0..polylines.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let mut polyline = polylines[start].clone();
+
+        // Grow the polyline at its end, then at its start
+        for _ in 0..2 {
+            loop {
+                let last = polyline[polyline.len() - 1];
+                let next = ends.get(&key(&last))
+                    .and_then(|candidates| candidates.iter().find(|&&i| !used[i]).cloned());
+                let next = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+
+                used[next] = true;
+                let piece = &polylines[next];
+                if key(&piece[0]) == key(&last) {
+                    polyline.extend(piece.iter().skip(1));
+                } else {
+                    polyline.extend(piece.iter().rev().skip(1));
+                }
+            }
+            polyline.reverse();
+        }
+
+        joined.push(polyline);
+    }
+
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The values of `f` on a grid of `width` × `height` integer positions
+    fn grid<F: Fn(f64, f64) -> f64>(width: usize, height: usize, f: F) -> Vec<f64> {
+        (0..width * height)
+            .map(|i| f((i % width) as f64, (i / width) as f64))
+            .collect()
+    }
+
+    #[test]
+    fn circle_is_closed() {
+        let center = Vector::new(10.0, 10.0);
+        let values = grid(21, 21, |x, y| (Vector::new(x, y) - center).norm());
+
+        let polylines = contour(&values, 21, 21, 5.0);
+        assert_eq!(polylines.len(), 1);
+        let circle = &polylines[0];
+        assert_eq!(circle[0], circle[circle.len() - 1]);
+        for point in circle {
+            assert!(((point - center).norm() - 5.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn ramp_crosses_the_grid() {
+        let values = grid(8, 6, |x, _| x);
+
+        let polylines = contour(&values, 8, 6, 3.5);
+        assert_eq!(polylines.len(), 1);
+        let line = &polylines[0];
+        assert_eq!(line.len(), 6);
+        assert!(line.iter().all(|point| point.x == 3.5));
+        let ends = (line[0].y.min(line[5].y), line[0].y.max(line[5].y));
+        assert_eq!(ends, (0.0, 5.0));
+    }
+
+    #[test]
+    fn saddle_goes_around_the_low_corners() {
+        // The center is at the level, so it is above it with the high corners
+        let values = [1.0, 0.0, 0.0, 1.0];
+
+        let mut polylines = contour(&values, 2, 2, 0.5);
+        assert_eq!(polylines.len(), 2);
+        for polyline in &mut polylines {
+            polyline.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+        }
+        polylines.sort_by(|a, b| (a[0].x, a[0].y).partial_cmp(&(b[0].x, b[0].y)).unwrap());
+        assert_eq!(
+            polylines[0],
+            vec![Vector::new(0.0, 0.5), Vector::new(0.5, 1.0)]
+        );
+        assert_eq!(
+            polylines[1],
+            vec![Vector::new(0.5, 0.0), Vector::new(1.0, 0.5)]
+        );
+    }
+
+    #[test]
+    fn skips_cells_without_values() {
+        let mut values = grid(8, 6, |x, _| x);
+        values[3 * 8 + 3] = f64::NAN;
+
+        // The line is cut by the two cells touching the missing value on its side
+        let polylines = contour(&values, 8, 6, 3.5);
+        assert_eq!(polylines.len(), 2);
+        assert_eq!(polylines.iter().map(|line| line.len()).sum::<usize>(), 5);
+    }
+
+    #[test]
+    fn levels_are_multiples_of_the_spacing() {
+        assert_eq!(even_levels(-1.0, 2.5, 1.0), vec![-1.0, 0.0, 1.0, 2.0]);
+        assert_eq!(even_levels(0.5, 0.7, 1.0), Vec::<f64>::new());
+
+        // The levels closest to zero are kept
+        let levels = even_levels(-1000.0, 10.0, 1.0);
+        assert_eq!(levels.len(), MAX_LEVELS);
+        assert_eq!(levels[0], -89.0);
+        assert_eq!(levels[levels.len() - 1], 10.0);
+        let levels = even_levels(-1000.0, 1000.0, 1.0);
+        assert_eq!((levels[0], levels[levels.len() - 1]), (-50.0, 49.0));
+    }
+
+    #[test]
+    fn pieces_are_joined() {
+        let center = Vector::new(10.0, 10.0);
+        let values = grid(21, 21, |x, y| (Vector::new(x, y) - center).norm());
+        let circle = contour(&values, 21, 21, 5.0).remove(0);
+
+        // Cut the circle in pieces sharing their ends, some reversed
+        let mut pieces: Vec<Vec<Vector>> =
+            circle.windows(5).step_by(4).map(|w| w.to_vec()).collect();
+        let last = (circle.len() - 1) / 4 * 4;
+        if last < circle.len() - 1 {
+            pieces.push(circle[last..].to_vec());
+        }
+        for piece in pieces.iter_mut().skip(1).step_by(2) {
+            piece.reverse();
+        }
+
+        let joined = join_polylines(pieces);
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0].len(), circle.len());
+        assert_eq!(joined[0][0], joined[0][joined[0].len() - 1]);
+    }
+}
@@ -0,0 +1,80 @@
+use super::{FieldLaw, Mode};
+use Vector;
+
+#[derive(Debug, Clone)]
+/// The parameters and the state of the motion of the free charges
+pub struct Dynamics {
+    // The duration of a step
+    pub time_step: f64,
+    // The forces are calculated as if the charges were always at least this far apart,
+    // so close encounters don't throw the charges away
+    pub softening: f64,
+    // The time simulated so far
+    pub time: f64,
+    // The acceleration of each free charge at the end of the last step
+    accelerations: Vec<Vector>,
+}
+
+impl Dynamics {
+    pub fn new() -> Dynamics {
+        Dynamics {
+            time_step: 0.01,
+            softening: 0.5,
+            time: 0.0,
+            accelerations: Vec::new(),
+        }
+    }
+
+    /// The accelerations of the last step, if they are still valid for `count` charges
+    pub fn accelerations(&self, count: usize) -> Option<&[Vector]> {
+        if self.accelerations.len() == count && count > 0 {
+            Some(&self.accelerations)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_accelerations(&mut self, accelerations: Vec<Vector>) {
+        self.accelerations = accelerations;
+    }
+
+    /// Forget the accelerations, so they are calculated again on the next step
+    pub fn invalidate(&mut self) {
+        self.accelerations.clear();
+    }
+}
+
+/// The force on a `charge` in `field`
+/// (a current is pushed along the direction of the field turned clockwise, I ẑ × B)
+pub fn get_force(mode: Mode, charge: f64, field: &Vector) -> Vector {
+    match mode {
+        Mode::Electrostatic => field * charge,
+        Mode::Magnetostatic => Vector::new(-field.y, field.x) * charge,
+    }
+}
+
+#[inline]
+/// The field of a single charge (or current) with distance `delta`,
+/// as if the distance was never shorter than `softening`
+pub fn softened_field(
+    mode: Mode,
+    law: FieldLaw,
+    charge: f64,
+    delta: &Vector,
+    softening: f64,
+) -> Vector {
+    let squared = delta.norm_squared() + softening * softening;
+    if squared == 0.0 {
+        return Vector::new(0.0, 0.0);
+    }
+
+    match (mode, law) {
+        (Mode::Electrostatic, FieldLaw::PointCharges) => delta * charge / squared.powf(1.5),
+        (Mode::Electrostatic, FieldLaw::LineCharges) => delta * charge / squared,
+        (Mode::Magnetostatic, _) => {
+            // The magnetic field is the field of line charges turned counterclockwise
+            let field = delta * charge / squared;
+            Vector::new(-field.y, field.x)
+        }
+    }
+}
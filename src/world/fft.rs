@@ -0,0 +1,189 @@
+use std::f64;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A complex number
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn zero() -> Complex {
+        Complex::new(0.0, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// In place radix-2 fast Fourier transform, the length of `data` must be a power of two
+/// The inverse transform is normalized, so that a transform followed by its inverse
+/// gives back the same data
+pub fn fft(data: &mut [Complex], inverse: bool) {
+    let length = data.len();
+    assert!(length.is_power_of_two());
+
+    // Reorder the data in bit reversed order
+    let mut j = 0;
+    for i in 1..length {
+        let mut bit = length >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    // Combine the transforms of growing size
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= length {
+        let angle = sign * 2.0 * f64::consts::PI / size as f64;
+        let root = Complex::new(angle.cos(), angle.sin());
+
+        for start in (0..length).step_by(size) {
+            let mut twiddle = Complex::new(1.0, 0.0);
+            for k in 0..size / 2 {
+                let even = data[start + k];
+                let odd = data[start + k + size / 2] * twiddle;
+
+                data[start + k] = even + odd;
+                data[start + k + size / 2] = even - odd;
+                twiddle = twiddle * root;
+            }
+        }
+
+        size *= 2;
+    }
+
+    if inverse {
+        let scale = 1.0 / length as f64;
+        for value in data.iter_mut() {
+            value.re *= scale;
+            value.im *= scale;
+        }
+    }
+}
+
+/// In place 2D transform of `data`, stored row by row
+/// Both `width` and `height` must be powers of two
+pub fn fft_2d(data: &mut [Complex], width: usize, height: usize, inverse: bool) {
+    for row in data.chunks_mut(width) {
+        fft(row, inverse);
+    }
+
+    let mut column = vec![Complex::zero(); height];
+    for x in 0..width {
+        for y in 0..height {
+            column[y] = data[y * width + x];
+        }
+
+        fft(&mut column, inverse);
+
+        for y in 0..height {
+            data[y * width + x] = column[y];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The discrete Fourier transform of `data`, summed directly
+    fn dft(data: &[Complex]) -> Vec<Complex> {
+        let length = data.len() as f64;
+        (0..data.len())
+            .map(|k| {
+                data.iter()
+                    .enumerate()
+                    .fold(Complex::zero(), |sum, (n, &value)| {
+                        let angle = -2.0 * f64::consts::PI * (k * n) as f64 / length;
+                        sum + value * Complex::new(angle.cos(), angle.sin())
+                    })
+            })
+            .collect()
+    }
+
+    fn assert_close(a: &[Complex], b: &[Complex]) {
+        for (a, b) in a.iter().zip(b) {
+            assert!(
+                (a.re - b.re).abs() < 1e-9 && (a.im - b.im).abs() < 1e-9,
+                "{:?} {:?}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn matches_direct_transform() {
+        let data: Vec<Complex> = (0..16)
+            .map(|i| Complex::new((i as f64 * 0.7).sin(), (i * i % 5) as f64))
+            .collect();
+
+        let mut transformed = data.clone();
+        fft(&mut transformed, false);
+        assert_close(&transformed, &dft(&data));
+
+        fft(&mut transformed, true);
+        assert_close(&transformed, &data);
+    }
+
+    #[test]
+    fn transforms_rows_and_columns() {
+        let (width, height) = (8, 4);
+        let data: Vec<Complex> = (0..width * height)
+            .map(|i| Complex::new((i % 7) as f64, -((i % 3) as f64)))
+            .collect();
+
+        let mut transformed = data.clone();
+        fft_2d(&mut transformed, width, height, false);
+
+        // The columns of the transformed rows
+        let mut rows = data.clone();
+        for row in rows.chunks_mut(width) {
+            let row_transform = dft(row);
+            row.copy_from_slice(&row_transform);
+        }
+        for x in 0..width {
+            let column: Vec<Complex> = (0..height).map(|y| rows[y * width + x]).collect();
+            for (y, value) in dft(&column).into_iter().enumerate() {
+                assert_close(&[transformed[y * width + x]], &[value]);
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use Vector;
+
+use std::f64;
+
+/// The longest piece of a surface along which the field is taken as constant, in tiles
+const FLUX_STEP: f64 = 0.1;
+/// The least number of sides of the polygon approximating a circle
+const CIRCLE_SIDES: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A closed curve in the plane, through which the flux is measured
+/// (the surface seen from above)
+pub enum Surface {
+    /// A polygon through the points, closed between the last point and the first
+    Polygon(Vec<Vector>),
+    Rectangle { min: Vector, max: Vector },
+    Circle { center: Vector, radius: f64 },
+}
+
+impl Surface {
+    /// A rectangle with the opposite corners `a` and `b`
+    pub fn rectangle(a: Vector, b: Vector) -> Surface {
+        Surface::Rectangle {
+            min: Vector::new(a.x.min(b.x), a.y.min(b.y)),
+            max: Vector::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    /// The corners of the polygon following the curve
+    pub fn points(&self) -> Vec<Vector> {
+        match *self {
+            Surface::Polygon(ref points) => points.clone(),
+            Surface::Rectangle { min, max } => vec![
+                min,
+                Vector::new(max.x, min.y),
+                max,
+                Vector::new(min.x, max.y),
+            ],
+            Surface::Circle { center, radius } => {
+                let circumference = 2.0 * f64::consts::PI * radius;
+                let sides = ((circumference / FLUX_STEP).ceil() as usize).max(CIRCLE_SIDES);
+
+                (0..sides)
+                    .map(|i| {
+                        let angle = 2.0 * f64::consts::PI * i as f64 / sides as f64;
+                        center + Vector::new(angle.cos(), angle.sin()) * radius
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Is `position` inside the curve?
+    /// (for a polygon crossing itself, inside the parts surrounded an odd number of times)
+    pub fn contains(&self, position: &Vector) -> bool {
+        match *self {
+            Surface::Polygon(ref points) => {
+                let mut inside = false;
+                for (i, a) in points.iter().enumerate() {
+                    let b = &points[(i + 1) % points.len()];
+                    if (a.y > position.y) != (b.y > position.y) {
+                        let x = a.x + (position.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                        if position.x < x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+            Surface::Rectangle { min, max } => {
+                position.x >= min.x
+                    && position.x < max.x
+                    && position.y >= min.y
+                    && position.y < max.y
+            }
+            Surface::Circle { center, radius } => (position - center).norm() < radius,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The flux out of a closed surface and the charge inside of it
+pub struct FluxReport {
+    // The flux of the displacement field D = εr E
+    pub flux: f64,
+    // The charge of the tiles and of the free charges inside the surface
+    pub enclosed: f64,
+    // The flux given by Gauss's law for the enclosed charge,
+    // `None` when the field law doesn't follow it
+    pub expected: Option<f64>,
+}
+
+/// The flux of `field` going out of the closed polygon through `points`
+/// `field` is taken in the middle of short pieces of the sides,
+/// and counts as zero where it's `None`
+pub fn flux<F>(points: &[Vector], field: F) -> f64
+where
+    F: Fn(&Vector) -> Option<Vector>,
+{
+    // The outward normal is on the right of the sides of a counterclockwise polygon
+    let area: f64 = points
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let b = &points[(i + 1) % points.len()];
+            a.x * b.y - b.x * a.y
+        })
+        .sum();
+    let orientation = area.signum();
+
+    let mut flux = 0.0;
+    for (i, a) in points.iter().enumerate() {
+        let b = &points[(i + 1) % points.len()];
+        let side = b - a;
+        let pieces = (side.norm() / FLUX_STEP).ceil().max(1.0) as usize;
+        // The normal, as long as a piece
+        let normal = Vector::new(side.y, -side.x) * orientation / pieces as f64;
+
+        for piece in 0..pieces {
+            let middle = a + side * ((piece as f64 + 0.5) / pieces as f64);
+            if let Some(field) = field(&middle) {
+                flux += field.dot(&normal);
+            }
+        }
+    }
+
+    flux
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The field at `position` of a line charge `q` at `charge`, whose potential is -q ln r
+    fn line_charge(charge: Vector, q: f64, position: &Vector) -> Option<Vector> {
+        let delta = position - charge;
+        Some(delta * q / delta.norm_squared())
+    }
+
+    #[test]
+    fn flux_of_a_line_charge() {
+        let q = 3.0;
+        let charge = Vector::new(2.3, 1.7);
+        let surfaces = [
+            Surface::Circle {
+                center: Vector::new(2.0, 2.0),
+                radius: 4.0,
+            },
+            Surface::rectangle(Vector::new(-1.0, 0.5), Vector::new(6.0, 3.0)),
+            // Clockwise, the flux still goes out
+            Surface::Polygon(vec![
+                Vector::new(0.0, 0.0),
+                Vector::new(1.0, 4.0),
+                Vector::new(5.0, 3.0),
+                Vector::new(4.0, -1.0),
+            ]),
+        ];
+
+        for surface in &surfaces {
+            assert!(surface.contains(&charge));
+            let points = surface.points();
+            let inside = flux(&points, |position| line_charge(charge, q, position));
+            let expected = 2.0 * f64::consts::PI * q;
+            assert!((inside - expected).abs() < 1e-3 * expected);
+
+            // The field lines of a charge outside go in and out of the curve
+            let outside = Vector::new(9.0, 8.0);
+            assert!(!surface.contains(&outside));
+            let outside = flux(&points, |position| line_charge(outside, q, position));
+            assert!(outside.abs() < 1e-3 * expected);
+        }
+    }
+}
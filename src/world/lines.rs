@@ -0,0 +1,368 @@
+use Vector;
+
+use std::f64;
+
+/// The distance between the points checked for obstacles along a step, in tiles
+const CHECK_DISTANCE: f64 = 0.25;
+/// How many times the entry into an obstacle is halved
+const BISECTIONS: usize = 10;
+
+/// The Dormand–Prince tableau, the weights of the previous stages in every stage
+const DP_STAGES: [[f64; 6]; 6] = [
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+/// The difference between the weights of the 5th and the 4th order solutions
+const DP_ERROR: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How the field lines are integrated
+pub enum Integrator {
+    /// Classic Runge–Kutta steps of `max_step`
+    RungeKutta4,
+    /// Dormand–Prince steps, growing and shrinking to keep the error under the tolerance
+    RungeKutta45,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// The parameters of the tracing of the field lines
+pub struct LineSettings {
+    pub integrator: Integrator,
+    // The largest error allowed on a step, in tiles
+    pub tolerance: f64,
+    // The bounds of the length of a step, in tiles
+    pub min_step: f64,
+    pub max_step: f64,
+    // The lines are stopped after this length, in tiles
+    pub max_length: f64,
+    // Every region of charge emits one line for this much charge, at least,
+    // and a line for a smaller charge
+    pub charge_per_line: f64,
+    // The most lines emitted by all the regions, the charge of a line grows past them
+    pub max_lines: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Connected charges of the same sign, emitting their field lines together
+pub struct ChargeRegion {
+    // The center of the charge
+    pub center: Vector,
+    // The total charge
+    pub charge: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A step taken along a field line
+pub struct LineStep {
+    pub position: Vector,
+    // The direction of the line at the start of the step
+    pub direction: Vector,
+    // The length of the step
+    pub length: f64,
+    // The length to try for the next step
+    pub next: f64,
+}
+
+impl LineSettings {
+    pub fn new() -> LineSettings {
+        LineSettings {
+            integrator: Integrator::RungeKutta45,
+            tolerance: 1e-3,
+            min_step: 0.01,
+            max_step: 0.5,
+            max_length: 500.0,
+            charge_per_line: 8.0,
+            max_lines: 100,
+        }
+    }
+
+    /// Take a step of about `length` from `position` along `direction`,
+    /// which gives the unit direction of the line or `None` where the line can't go
+    /// The step is shortened until it has a small enough error and stays where the line can go,
+    /// `None` is returned when even the shortest step fails
+    pub fn step<F>(&self, position: &Vector, length: f64, direction: F) -> Option<LineStep>
+    where
+        F: Fn(&Vector) -> Option<Vector>,
+    {
+        let start = direction(position)?;
+        let mut length = match self.integrator {
+            Integrator::RungeKutta4 => self.max_step,
+            Integrator::RungeKutta45 => length.max(self.min_step).min(self.max_step),
+        };
+
+        loop {
+            let result = match self.integrator {
+                Integrator::RungeKutta4 => {
+                    runge_kutta_4(position, &start, length, &direction).map(|end| (end, 0.0))
+                }
+                Integrator::RungeKutta45 => dormand_prince(position, &start, length, &direction),
+            };
+
+            match result {
+                Some((end, error)) if error <= self.tolerance || length <= self.min_step => {
+                    // Grow the step as much as the error allows
+                    let factor = if error > 0.0 {
+                        0.9 * (self.tolerance / error).powf(0.2)
+                    } else {
+                        5.0
+                    };
+                    let next = (length * factor.max(0.2).min(5.0))
+                        .max(self.min_step)
+                        .min(self.max_step);
+
+                    return Some(LineStep {
+                        position: end,
+                        direction: start,
+                        length,
+                        next,
+                    });
+                }
+                Some((_, error)) => {
+                    let factor = 0.9 * (self.tolerance / error).powf(0.25);
+                    length = (length * factor.max(0.1)).max(self.min_step);
+                }
+                None if length > self.min_step => length = (length / 2.0).max(self.min_step),
+                None => return None,
+            }
+        }
+    }
+}
+
+/// A classic Runge–Kutta step of `length`, `start` is the direction at `position`
+fn runge_kutta_4<F>(position: &Vector, start: &Vector, length: f64, direction: &F) -> Option<Vector>
+where
+    F: Fn(&Vector) -> Option<Vector>,
+{
+    let k1 = *start;
+    let k2 = direction(&(position + k1 * length / 2.0))?;
+    let k3 = direction(&(position + k2 * length / 2.0))?;
+    let k4 = direction(&(position + k3 * length))?;
+
+    Some(position + (k1 + k2 * 2.0 + k3 * 2.0 + k4) * length / 6.0)
+}
+
+/// A Dormand–Prince step of `length`, `start` is the direction at `position`
+/// Returns the end of the step (5th order) and the estimate of its error
+fn dormand_prince<F>(
+    position: &Vector,
+    start: &Vector,
+    length: f64,
+    direction: &F,
+) -> Option<(Vector, f64)>
+where
+    F: Fn(&Vector) -> Option<Vector>,
+{
+    let mut stages = [*start; 7];
+    for (i, weights) in DP_STAGES.iter().enumerate() {
+        let mut offset = Vector::new(0.0, 0.0);
+        for (stage, weight) in stages.iter().zip(weights.iter()).take(i + 1) {
+            offset += stage * *weight;
+        }
+        stages[i + 1] = direction(&(position + offset * length))?;
+    }
+
+    // The last stage is taken at the end of the step, with the weights of the solution
+    let mut end = Vector::new(0.0, 0.0);
+    for (stage, weight) in stages.iter().zip(DP_STAGES[5].iter()) {
+        end += stage * *weight;
+    }
+    let mut error = Vector::new(0.0, 0.0);
+    for (stage, weight) in stages.iter().zip(DP_ERROR.iter()) {
+        error += stage * *weight;
+    }
+
+    Some((position + end * length, (error * length).norm()))
+}
+
+/// The first point between `start` and `end` where `blocked` is true, if any
+/// (found within a small fraction of a tile, on the blocked side)
+pub fn first_blocked<F>(start: &Vector, end: &Vector, blocked: F) -> Option<Vector>
+where
+    F: Fn(&Vector) -> bool,
+{
+    if blocked(start) {
+        return Some(*start);
+    }
+
+    let checks = ((end - start).norm() / CHECK_DISTANCE).ceil().max(1.0) as usize;
+    let mut free = *start;
+    for i in 1..checks + 1 {
+        let point = start + (end - start) * (i as f64 / checks as f64);
+        if !blocked(&point) {
+            free = point;
+            continue;
+        }
+
+        // Narrow the entry down between the last free point and the blocked one
+        let mut hit = point;
+        for _ in 0..BISECTIONS {
+            let middle = (free + hit) / 2.0;
+            if blocked(&middle) {
+                hit = middle;
+            } else {
+                free = middle;
+            }
+        }
+        return Some(hit);
+    }
+
+    None
+}
+
+/// The directions of `count` lines evenly spaced around a charge
+pub fn even_angles(count: usize) -> Vec<f64> {
+    (0..count)
+        .map(|i| 2.0 * f64::consts::PI * i as f64 / count as f64)
+        .collect()
+}
+
+/// `count` points evenly spaced along `sides` following each other around a region,
+/// `offset` away from them on their right (out of the region)
+pub fn even_points(sides: &[(Vector, Vector)], count: usize, offset: f64) -> Vec<Vector> {
+    let perimeter: f64 = sides.iter().map(|&(start, end)| (end - start).norm()).sum();
+    let spacing = perimeter / count as f64;
+
+    let mut points = Vec::new();
+    // The length of the sides before the current one, and of the sides before the next point
+    let mut covered = 0.0;
+    let mut next = spacing / 2.0;
+    for &(start, end) in sides {
+        let length = (end - start).norm();
+        let normal = Vector::new(end.y - start.y, start.x - end.x) / length;
+        while next < covered + length && points.len() < count {
+            points.push(start + (end - start) * ((next - covered) / length) + normal * offset);
+            next += spacing;
+        }
+        covered += length;
+    }
+
+    points
+}
+
+/// Take away the point in `points` closest to `point`
+pub fn claim_point(points: &mut Vec<Vector>, point: &Vector) {
+    let closest = points
+        .iter()
+        .enumerate()
+        .min_by(|&(_, a), &(_, b)| (a - point).norm().partial_cmp(&(b - point).norm()).unwrap())
+        .map(|(i, _)| i);
+    if let Some(i) = closest {
+        points.remove(i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The unit direction of a field circling counterclockwise around the origin
+    fn circling(position: &Vector) -> Option<Vector> {
+        let norm = position.norm();
+        if norm == 0.0 {
+            None
+        } else {
+            Some(Vector::new(-position.y, position.x) / norm)
+        }
+    }
+
+    #[test]
+    fn dormand_prince_follows_a_circle() {
+        let start = Vector::new(1.0, 0.0);
+        let (end, error) =
+            dormand_prince(&start, &circling(&start).unwrap(), 0.5, &circling).unwrap();
+
+        assert!((end - Vector::new(0.5f64.cos(), 0.5f64.sin())).norm() < 1e-6);
+        assert!(error < 1e-5);
+    }
+
+    #[test]
+    fn dormand_prince_is_of_fifth_order() {
+        let start = Vector::new(1.0, 0.0);
+        let direction = circling(&start).unwrap();
+
+        // Halving the step divides the error by at least about 2^5,
+        // and the error is always less than its estimate
+        let mut last: Option<(f64, f64)> = None;
+        for &length in &[0.4, 0.2, 0.1] {
+            let (end, estimate) = dormand_prince(&start, &direction, length, &circling).unwrap();
+            let error = (end - Vector::new(length.cos(), length.sin())).norm();
+            assert!(error < estimate);
+
+            if let Some((last_error, last_estimate)) = last {
+                assert!(last_error / error > 25.0);
+                assert!(last_estimate / estimate > 25.0);
+            }
+            last = Some((error, estimate));
+        }
+    }
+
+    #[test]
+    fn steps_keep_the_error_under_the_tolerance() {
+        let mut settings = LineSettings::new();
+        settings.tolerance = 1e-8;
+        settings.max_step = 2.0;
+
+        let start = Vector::new(1.0, 0.0);
+        let step = settings.step(&start, 2.0, circling).unwrap();
+        assert!(step.length < 2.0);
+        let exact = Vector::new(step.length.cos(), step.length.sin());
+        assert!((step.position - exact).norm() < 1e-7);
+
+        // In a straight field there is no error, and the next step grows five times
+        let straight = |_: &Vector| Some(Vector::new(1.0, 0.0));
+        let step = settings.step(&start, 0.1, straight).unwrap();
+        assert_eq!(step.position, Vector::new(1.1, 0.0));
+        assert_eq!(step.next, 0.5);
+    }
+
+    #[test]
+    fn steps_stop_where_the_line_cant_go() {
+        let settings = LineSettings::new();
+        let bounded = |position: &Vector| {
+            if position.x < 1.2 {
+                Some(Vector::new(1.0, 0.0))
+            } else {
+                None
+            }
+        };
+
+        let step = settings.step(&Vector::new(1.0, 0.0), 0.5, bounded).unwrap();
+        assert!(step.position.x < 1.2 && step.length < 0.5);
+        assert!(settings
+            .step(&Vector::new(1.5, 0.0), 0.5, bounded)
+            .is_none());
+    }
+}
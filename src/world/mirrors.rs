@@ -0,0 +1,193 @@
+use super::FieldLaw;
+use Vector;
+
+/// How many times the images are reflected in the other mirrors
+const MIRROR_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A grounded conductor whose effect on the charges is exactly replaced by image charges
+pub enum Mirror {
+    /// An infinite plane through `point`, perpendicular to the world
+    /// The side `normal` points to is free, the other side is the conductor
+    Plane { point: Vector, normal: Vector },
+    /// A circle, the section of a sphere with point charges
+    /// and of a cylinder with line charges
+    /// The inside of the circle is the conductor
+    Circle { center: Vector, radius: f64 },
+}
+
+impl Mirror {
+    /// The plane through `point` with the free side towards `normal`
+    pub fn plane(point: Vector, normal: Vector) -> Mirror {
+        Mirror::Plane {
+            point,
+            normal: normal.normalize(),
+        }
+    }
+
+    pub fn circle(center: Vector, radius: f64) -> Mirror {
+        Mirror::Circle { center, radius }
+    }
+
+    /// Is `position` inside the conductor?
+    pub fn contains(&self, position: &Vector) -> bool {
+        match *self {
+            Mirror::Plane { point, normal } => (position - point).dot(&normal) < 0.0,
+            Mirror::Circle { center, radius } => (position - center).norm() < radius,
+        }
+    }
+
+    /// The distance from `position` to the surface of the conductor
+    pub fn distance(&self, position: &Vector) -> f64 {
+        match *self {
+            Mirror::Plane { point, normal } => (position - point).dot(&normal).abs(),
+            Mirror::Circle { center, radius } => ((position - center).norm() - radius).abs(),
+        }
+    }
+
+    /// A point on the surface of the conductor
+    pub fn surface_point(&self) -> Vector {
+        match *self {
+            Mirror::Plane { point, .. } => point,
+            Mirror::Circle { center, radius } => center + Vector::new(radius, 0.0),
+        }
+    }
+
+    /// The image of `charge` at `position` (which must be outside of the conductor)
+    ///
+    /// The surface of a plane is then at zero potential with both laws.
+    /// The surface of a sphere (point charges) is at zero potential too,
+    /// the surface of a cylinder (line charges) is an equipotential
+    /// but, like every 2D potential, its value depends on the origin of the logarithm.
+    pub fn image(&self, position: &Vector, charge: f64, law: FieldLaw) -> (Vector, f64) {
+        match *self {
+            Mirror::Plane { point, normal } => {
+                let distance = (position - point).dot(&normal);
+                (position - normal * 2.0 * distance, -charge)
+            }
+            Mirror::Circle { center, radius } => {
+                let delta = position - center;
+                let distance = delta.norm();
+                // The inverse of the position in the circle
+                let image = center + delta * (radius * radius / (distance * distance));
+                let image_charge = match law {
+                    FieldLaw::PointCharges => -charge * radius / distance,
+                    FieldLaw::LineCharges => -charge,
+                };
+                (image, image_charge)
+            }
+        }
+    }
+}
+
+/// The images of `charge` at `position` in the `mirrors`
+/// With several mirrors the images are reflected again in the other mirrors
+/// (up to `MIRROR_DEPTH` times), so the field is only exact with a single mirror
+pub fn images(
+    position: &Vector,
+    charge: f64,
+    mirrors: &[Mirror],
+    law: FieldLaw,
+) -> Vec<(Vector, f64)> {
+    let mut images = Vec::new();
+    // The last reflected images, with the mirror that made them
+    let mut generation = vec![(*position, charge, None)];
+
+    for _ in 0..MIRROR_DEPTH {
+        let mut next = Vec::new();
+        for &(position, charge, parent) in &generation {
+            for (i, mirror) in mirrors.iter().enumerate() {
+                if Some(i) != parent && !mirror.contains(&position) {
+                    let (image, image_charge) = mirror.image(&position, charge, law);
+                    next.push((image, image_charge, Some(i)));
+                }
+            }
+        }
+
+        images.extend(next.iter().map(|&(position, charge, _)| (position, charge)));
+        generation = next;
+    }
+
+    images
+}
+
+/// Add the images of `sources` in the `mirrors`
+/// The charges inside of a conductor are screened by it, so they are removed
+pub fn add_images(
+    sources: &[(Vector, f64)],
+    mirrors: &[Mirror],
+    law: FieldLaw,
+) -> Vec<(Vector, f64)> {
+    let mut all = Vec::with_capacity(sources.len() * (mirrors.len() + 1));
+    for &(position, charge) in sources {
+        if !mirrors.iter().any(|mirror| mirror.contains(&position)) {
+            all.push((position, charge));
+            all.extend(images(&position, charge, mirrors, law));
+        }
+    }
+
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64;
+
+    /// The potential of line charges (-q ln r) and of point charges (q / r) at `position`
+    fn potentials(sources: &[(Vector, f64)], position: &Vector) -> (f64, f64) {
+        sources
+            .iter()
+            .fold((0.0, 0.0), |(line, point), &(source, charge)| {
+                let distance = (position - source).norm();
+                (line - charge * distance.ln(), point + charge / distance)
+            })
+    }
+
+    /// The potentials of the charges with their images at 32 points of the surface
+    fn surface_potentials(mirror: Mirror, law: FieldLaw) -> Vec<f64> {
+        let charges = [(Vector::new(3.0, 4.0), 1.0), (Vector::new(-2.0, 5.0), -0.5)];
+        let sources = add_images(&charges, &[mirror], law);
+        (0..32)
+            .map(|i| {
+                let position = match mirror {
+                    Mirror::Plane { point, normal } => {
+                        point + Vector::new(-normal.y, normal.x) * (i as f64 - 16.0)
+                    }
+                    Mirror::Circle { center, radius } => {
+                        let angle = 2.0 * f64::consts::PI * i as f64 / 32.0;
+                        center + Vector::new(angle.cos(), angle.sin()) * radius
+                    }
+                };
+                let (line, point) = potentials(&sources, &position);
+                match law {
+                    FieldLaw::LineCharges => line,
+                    FieldLaw::PointCharges => point,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn grounded_mirrors_are_equipotentials() {
+        let plane = Mirror::plane(Vector::new(0.0, 1.0), Vector::new(0.2, 1.0));
+        let circle = Mirror::circle(Vector::new(0.5, 0.0), 2.0);
+
+        for &law in &[FieldLaw::LineCharges, FieldLaw::PointCharges] {
+            for potential in surface_potentials(plane, law) {
+                assert!(potential.abs() < 1e-12);
+            }
+        }
+        for potential in surface_potentials(circle, FieldLaw::PointCharges) {
+            assert!(potential.abs() < 1e-12);
+        }
+
+        // The potential of a cylinder depends on the origin of the logarithm,
+        // it's only the same all around
+        let potentials = surface_potentials(circle, FieldLaw::LineCharges);
+        for potential in &potentials {
+            assert!((potential - potentials[0]).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,3018 @@
+pub mod boundaries;
+pub mod capacitance;
+pub mod chunks;
+pub mod contours;
+pub mod dynamics;
+pub mod fft;
+pub mod gauss;
+pub mod lines;
+pub mod mirrors;
+pub mod path;
+pub mod solver;
+pub mod trajectory;
+pub mod tree;
+pub mod units;
+pub mod viewport;
+
+use self::boundaries::{Boundaries, Boundary};
+use self::capacitance::{conductor_groups, ConductorBody};
+use self::chunks::{chunk_of, Chunks, CHUNK_SIZE};
+use self::contours::{contour, even_levels, join_polylines};
+use self::dynamics::{get_force, softened_field, Dynamics};
+use self::fft::{fft_2d, Complex};
+use self::gauss::{FluxReport, Surface};
+use self::lines::{
+    claim_point, even_angles, even_points, first_blocked, ChargeRegion, LineSettings,
+};
+use self::mirrors::{add_images, images, Mirror};
+use self::path::{integrate, LineIntegral};
+use self::solver::{Method, Solver};
+use self::trajectory::{TestParticle, Trajectory, TrajectoryPoint, TrajectorySettings};
+use self::tree::QuadTree;
+use self::units::Units;
+use self::viewport::{ViewField, Viewport};
+use parallel::{for_each_row, Progress};
+use Vector;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::f64;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The source of the generations of the fields
+static NEXT_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// A new number identifying a field, different from all the others
+fn next_generation() -> usize {
+    NEXT_GENERATION.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+/// The cells of a chunk of the field grid, row by row
+/// Every quantity has its own buffer, so the loops along a row go through contiguous numbers
+/// (the cells out of the grid, on the chunks of the edges, are not numbers)
+struct FieldChunk {
+    ex: Vec<f64>,
+    ey: Vec<f64>,
+    potential: Vec<f64>,
+}
+
+/// The cells of a row of the field grid inside of a chunk, as given by `for_each_span`
+struct RowSpan<'a> {
+    // The field grid coordinates of the first cell
+    x: usize,
+    y: usize,
+    ex: &'a mut [f64],
+    ey: &'a mut [f64],
+    potential: &'a mut [f64],
+}
+
+impl<'a> RowSpan<'a> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.potential.len()
+    }
+
+    #[inline]
+    fn get(&self, i: usize) -> (Vector, f64) {
+        (Vector::new(self.ex[i], self.ey[i]), self.potential[i])
+    }
+
+    #[inline]
+    fn set(&mut self, i: usize, (field, potential): (Vector, f64)) {
+        self.ex[i] = field.x;
+        self.ey[i] = field.y;
+        self.potential[i] = potential;
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A field grid which can be bigger than the tiles grid
+/// The cells are stored in chunks covering `CHUNK_SIZE` tiles, only the evaluated chunks
+/// are allocated and the cells out of them can't be read
+pub struct FieldGrid {
+    ratio: u8,
+    // The size of the grid, in cells
+    width: usize,
+    height: usize,
+    chunks: HashMap<(usize, usize), FieldChunk>,
+}
+
+impl FieldGrid {
+    fn new(original_width: usize, original_height: usize, ratio: u8) -> FieldGrid {
+        FieldGrid {
+            ratio,
+            width: original_width * ratio as usize,
+            height: original_height * ratio as usize,
+            chunks: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    /// The side of a chunk, in cells
+    fn side(&self) -> usize {
+        CHUNK_SIZE * self.ratio as usize
+    }
+
+    #[inline]
+    /// The chunk holding the cell (`x`, `y`) and the index of the cell in it
+    fn locate(&self, x: usize, y: usize) -> ((usize, usize), usize) {
+        let side = self.side();
+        ((x / side, y / side), (y % side) * side + x % side)
+    }
+
+    #[inline]
+    // Get a field cell using the field grid coordinates, if its chunk is allocated
+    pub fn cell(&self, x: usize, y: usize) -> Option<(Vector, f64)> {
+        let (chunk, index) = self.locate(x, y);
+        self.chunks.get(&chunk).map(|chunk| {
+            (
+                Vector::new(chunk.ex[index], chunk.ey[index]),
+                chunk.potential[index],
+            )
+        })
+    }
+
+    #[inline]
+    // Get a field tile using tiles' coordinates, if its chunk is allocated
+    pub fn get(&self, position: &Vector) -> Option<(Vector, f64)> {
+        let x = position.x * self.ratio as f64;
+        let y = position.y * self.ratio as f64;
+
+        self.cell(x as usize, y as usize)
+    }
+
+    // Get the field at a position in tiles' coordinates,
+    // interpolated between the centers of the four closest field tiles
+    // if all of them are allocated
+    pub fn interpolate(&self, position: &Vector) -> Option<(Vector, f64)> {
+        let (width, height) = (self.width, self.height);
+        let x = (position.x * self.ratio as f64 - 0.5).max(0.0);
+        let y = (position.y * self.ratio as f64 - 0.5).max(0.0);
+
+        let (x0, y0) = ((x as usize).min(width - 1), (y as usize).min(height - 1));
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (tx, ty) = ((x - x0 as f64).min(1.0), (y - y0 as f64).min(1.0));
+
+        let corners = [
+            (self.cell(x0, y0)?, (1.0 - tx) * (1.0 - ty)),
+            (self.cell(x1, y0)?, tx * (1.0 - ty)),
+            (self.cell(x0, y1)?, (1.0 - tx) * ty),
+            (self.cell(x1, y1)?, tx * ty),
+        ];
+
+        // Next to a singular tile there is nothing to interpolate
+        if corners
+            .iter()
+            .any(|&((field, potential), _)| !field.norm().is_finite() || !potential.is_finite())
+        {
+            return self.get(position);
+        }
+
+        Some(corners.iter().fold(
+            (Vector::new(0.0, 0.0), 0.0),
+            |(field, potential), &((corner_field, corner_potential), weight)| {
+                (field + corner_field * weight, potential + corner_potential * weight)
+            },
+        ))
+    }
+
+    /// The allocated chunks, sorted
+    pub fn chunks(&self) -> Vec<(usize, usize)> {
+        let mut chunks: Vec<(usize, usize)> = self.chunks.keys().cloned().collect();
+        chunks.sort();
+        chunks
+    }
+
+    pub fn has_chunk(&self, chunk: (usize, usize)) -> bool {
+        self.chunks.contains_key(&chunk)
+    }
+
+    /// The potential of every cell of the allocated chunks (not numbers out of the grid)
+    fn potentials<'a>(&'a self) -> impl Iterator<Item = f64> + 'a {
+        self.chunks
+            .values()
+            .flat_map(|chunk| chunk.potential.iter().cloned())
+    }
+
+    /// Allocate the chunk with an empty field
+    fn allocate(&mut self, chunk: (usize, usize)) {
+        let side = self.side();
+        let (width, height) = (self.width, self.height);
+        let cells: Vec<f64> = (0..side * side)
+            .map(|i| {
+                let (x, y) = (chunk.0 * side + i % side, chunk.1 * side + i / side);
+                if x < width && y < height {
+                    0.0
+                } else {
+                    f64::NAN
+                }
+            })
+            .collect();
+        self.chunks.insert(
+            chunk,
+            FieldChunk {
+                ex: cells.clone(),
+                ey: cells.clone(),
+                potential: cells,
+            },
+        );
+    }
+
+    fn free(&mut self, chunk: (usize, usize)) {
+        self.chunks.remove(&chunk);
+    }
+
+    /// Move the chunks of `other` to this grid
+    fn merge(&mut self, other: FieldGrid) {
+        self.chunks.extend(other.chunks);
+    }
+
+    /// The number of cells in the allocated chunks
+    fn allocated_cells(&self) -> usize {
+        self.chunks.len() * self.side() * self.side()
+    }
+
+    /// Call `f` on the part of every row of the grid inside of every allocated chunk
+    /// The chunks are processed in parallel, every chunk advances the `progress` by one
+    fn for_each_span<F>(&mut self, progress: &Progress, f: F)
+    where
+        F: Fn(RowSpan) + Sync + Send,
+    {
+        let side = self.side();
+        let (width, height) = (self.width, self.height);
+        progress.start(self.chunks.len());
+
+        let mut chunks: Vec<_> = self.chunks.iter_mut().collect();
+        for_each_row(&mut chunks, |_, chunk| {
+            let (cx, cy) = *chunk.0;
+            let chunk = &mut chunk.1;
+            let (x, y0) = (cx * side, cy * side);
+            // The chunks on the edges are cut by the grid
+            let columns = side.min(width - x);
+            let rows = side.min(height - y0);
+
+            let rows = chunk
+                .ex
+                .chunks_mut(side)
+                .zip(chunk.ey.chunks_mut(side))
+                .zip(chunk.potential.chunks_mut(side))
+                .take(rows);
+            for (row, ((ex, ey), potential)) in rows.enumerate() {
+                f(RowSpan {
+                    x,
+                    y: y0 + row,
+                    ex: &mut ex[..columns],
+                    ey: &mut ey[..columns],
+                    potential: &mut potential[..columns],
+                });
+            }
+
+            progress.advance(1);
+        });
+    }
+
+    /// Call `f` on every cell of the grid in the allocated chunks, with its coordinates
+    fn for_each_cell<F>(&mut self, progress: &Progress, f: F)
+    where
+        F: Fn(usize, usize, &mut (Vector, f64)) + Sync + Send,
+    {
+        self.for_each_span(progress, |mut span| {
+            for i in 0..span.len() {
+                let mut cell = span.get(i);
+                f(span.x + i, span.y, &mut cell);
+                span.set(i, cell);
+            }
+        });
+    }
+}
+
+/// The distance between the points tried for the start of a field line, in tiles
+const SEED_STEP: f64 = 0.05;
+/// The distance from a free charge at which field lines start and end
+pub const CHARGE_RADIUS: f64 = 0.4;
+/// How many times a field line can cross the periodic edges
+const MAX_WRAPS: usize = 8;
+/// The convolution is used when there are more updated charges than
+/// this times the logarithm of the size of the field grid
+const CONVOLUTION_THRESHOLD: f64 = 32.0;
+/// The largest tolerance of the solver when calculating the capacitance
+const CAPACITANCE_TOLERANCE: f64 = 1e-6;
+/// The tiles solved around the materials with open edges, past half of their size
+const SOLVER_MARGIN: f64 = 8.0;
+/// The side of the box solved around the materials is a multiple of this, in tiles
+const SOLVER_BLOCK: usize = 16;
+/// The most field cells the solver is given, bigger solves would take too long
+/// (the whole world is solved when an edge isn't open or there is a plane)
+const MAX_SOLVED_CELLS: usize = 1 << 22;
+/// With the Barnes–Hut evaluator the whole field is evaluated again
+/// when more than this many charges changed
+const TREE_THRESHOLD: usize = 64;
+/// The side of the unbounded canvas, in tiles, so big that its edges are never reached
+const CANVAS_SIZE: u32 = 1 << 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The law used to calculate the field of a single charge
+pub enum FieldLaw {
+    /// Point charges in a plane, the potential goes as 1/r and the field as 1/r²
+    PointCharges,
+    /// Infinite line charges perpendicular to the plane (true 2D electrostatics),
+    /// the potential goes as -ln(r) and the field as 1/r
+    LineCharges,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// What the charges of the world represent
+pub enum Mode {
+    /// Electric charges, the field is the electric field
+    Electrostatic,
+    /// Wires carrying currents through the plane (positive currents come out of the screen),
+    /// the field is the magnetic field and the potential is the vector potential Az
+    Magnetostatic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How the field of the charges is evaluated
+pub enum Evaluator {
+    /// Every charge is summed exactly
+    Direct,
+    /// Far groups of charges are approximated by a quadtree (Barnes–Hut),
+    /// the smaller `theta` the more accurate (0 is exact)
+    BarnesHut { theta: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How the field is sampled between the field tiles
+pub enum Sampling {
+    /// Interpolated between the centers of the four closest field tiles
+    Bilinear,
+    /// The field of the charges is summed exactly at the position,
+    /// the field of the conductors, the dielectrics and the edges is interpolated
+    Exact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The field and the potential at some position
+pub struct FieldSample {
+    pub field: Vector,
+    pub potential: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The error of an approximated field compared to the exact sum
+pub struct ErrorReport {
+    // The largest and mean relative error of the field
+    pub max_error: f64,
+    pub mean_error: f64,
+    // The number of points compared
+    pub samples: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A charge free to sit anywhere in the world, with any magnitude
+pub struct Charge {
+    pub position: Vector,
+    pub q: f64,
+    pub mass: f64,
+    pub velocity: Vector,
+    // A pinned charge never moves
+    pub pinned: bool,
+}
+
+impl Charge {
+    pub fn new(position: Vector, q: f64) -> Charge {
+        Charge {
+            position,
+            q,
+            mass: 1.0,
+            velocity: Vector::new(0.0, 0.0),
+            pinned: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The world containing all information for the simulation
+pub struct World {
+    pub height: u32,
+    pub width: u32,
+    // The charge of each tile
+    tiles: Chunks<i8>,
+    // The fixed potential of each tile, `None` when the tile is not a conductor
+    conductors: Chunks<Option<f64>>,
+    // The relative permittivity of each tile
+    permittivity: Chunks<f64>,
+    // The charged tiles and the conductor tiles, (x, y)
+    charge_index: BTreeSet<(usize, usize)>,
+    conductor_index: BTreeSet<(usize, usize)>,
+    // The chunks being viewed, (min x, min y, max x, max y), all of them when `None`
+    // The field is only evaluated on them and around the charges and the materials
+    view: Option<(usize, usize, usize, usize)>,
+    // Is the world an unbounded canvas? (see `new_unbounded`)
+    unbounded: bool,
+    // (old_charge, x, y)
+    pub updated_tiles: Vec<(i8, usize, usize)>,
+    // The free charges, which are not bound to the tiles
+    pub charges: Vec<Charge>,
+    // (position, added charge), a removed charge adds its opposite
+    pub updated_charges: Vec<(Vector, f64)>,
+    // The field of the charges with the field induced by the materials and the mirrors,
+    // left empty when nothing is induced (see `field`)
+    field: FieldGrid,
+    induced: bool,
+    // The law used to calculate the field of the charges
+    law: FieldLaw,
+    // Are the charges electric charges or currents?
+    mode: Mode,
+    // The conditions on the edges of the world
+    boundaries: Boundaries,
+    // The grounded planes and circles, replaced by image charges
+    mirrors: Vec<Mirror>,
+    // How the field of the charges is evaluated
+    evaluator: Evaluator,
+    // The tree of all the charges, when using the Barnes–Hut evaluator
+    tree: Option<QuadTree>,
+    // All the charges with their images and the potential of every mirror,
+    // as they were when the field was last calculated
+    sources: Arc<Vec<(Vector, f64)>>,
+    mirror_potentials: Vec<f64>,
+    // The field generated only by the charges
+    free_field: FieldGrid,
+    pub solver: Solver,
+    // The conductors must be solved again
+    solve_pending: bool,
+    // Changes every time the field is reset,
+    // so a field calculated on an older copy of the world can be recognized
+    generation: usize,
+    // Changes every time the conductors, the dielectrics or the field are reset,
+    // and the capacitance matrix with the one it was calculated for
+    materials_generation: usize,
+    capacitance: Option<(usize, Vec<Vec<f64>>)>,
+    // The progress of the field calculation, shared by all the copies of the world
+    pub progress: Arc<Progress>,
+    // The motion of the free charges
+    pub dynamics: Dynamics,
+    // How the field lines are traced
+    pub line_settings: LineSettings,
+    // How the field is sampled between the field tiles
+    pub sampling: Sampling,
+    // The physical scale of the world, for the readouts
+    pub units: Units,
+}
+
+impl World {
+    pub fn new_empty(width: u32, height: u32, resolution: u8) -> World {
+        // Init the tiles and field to an empty space
+        let tiles = Chunks::new(width as usize, height as usize, 0);
+        let conductors = Chunks::new(width as usize, height as usize, None);
+        let permittivity = Chunks::new(width as usize, height as usize, 1.0);
+
+        // The field_ratio must be an odd number
+        // So there are always centered tiles in the subdivision
+        let field_ratio = 2 * resolution - 1;
+        let field = FieldGrid::new(width as usize, height as usize, field_ratio);
+        let free_field = field.clone();
+        let solver = Solver::new(field_ratio as usize);
+        let updated_tiles = Vec::new();
+
+        World {
+            height,
+            width,
+            tiles,
+            conductors,
+            permittivity,
+            charge_index: BTreeSet::new(),
+            conductor_index: BTreeSet::new(),
+            view: None,
+            unbounded: false,
+            updated_tiles,
+            charges: Vec::new(),
+            updated_charges: Vec::new(),
+            field,
+            law: FieldLaw::PointCharges,
+            mode: Mode::Electrostatic,
+            boundaries: Boundaries::all(Boundary::Open),
+            mirrors: Vec::new(),
+            evaluator: Evaluator::Direct,
+            tree: None,
+            sources: Arc::new(Vec::new()),
+            mirror_potentials: Vec::new(),
+            free_field,
+            solver,
+            induced: false,
+            solve_pending: false,
+            generation: next_generation(),
+            materials_generation: next_generation(),
+            capacitance: None,
+            progress: Arc::new(Progress::new()),
+            dynamics: Dynamics::new(),
+            line_settings: LineSettings::new(),
+            sampling: Sampling::Bilinear,
+            units: Units::new(),
+        }
+    }
+
+    /// A world without edges to pick, where the charges can be placed anywhere
+    /// There is no field grid, the field of the charges is evaluated where it's sampled
+    /// (see `evaluate_view`), so the canvas can't hold conductors, dielectrics or edges
+    pub fn new_unbounded(resolution: u8) -> World {
+        let mut world = World::new_empty(CANVAS_SIZE, CANVAS_SIZE, resolution);
+        world.unbounded = true;
+        world
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.unbounded
+    }
+
+    pub fn set_resolution(&mut self, resolution: u8) {
+        // The field_ratio must be an odd number
+        // So there are always centered tiles in the subdivision
+        let field_ratio = 2 * resolution - 1;
+        self.reset_field(field_ratio);
+    }
+
+    pub fn resolution(&self) -> u8 {
+        (self.field.ratio + 1) / 2
+    }
+
+    /// Change the law of the field, the whole field will be calculated again
+    /// The conductors and the dielectrics are solved in 2D, so they keep the line charges
+    /// (returns false when the law can't change)
+    pub fn set_law(&mut self, law: FieldLaw) -> bool {
+        if law == FieldLaw::PointCharges && self.holds_materials() {
+            return false;
+        }
+        if self.law != law {
+            self.law = law;
+            let field_ratio = self.field.ratio;
+            self.reset_field(field_ratio);
+        }
+        true
+    }
+
+    pub fn law(&self) -> FieldLaw {
+        self.law
+    }
+
+    /// Change what the charges represent, the whole field will be calculated again
+    pub fn set_mode(&mut self, mode: Mode) {
+        if self.mode != mode {
+            self.mode = mode;
+            let field_ratio = self.field.ratio;
+            self.reset_field(field_ratio);
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Change the conditions on the edges, the whole field will be calculated again
+    /// The unbounded canvas keeps open edges
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) {
+        if self.boundaries != boundaries && !self.unbounded {
+            self.boundaries = boundaries;
+            let field_ratio = self.field.ratio;
+            self.reset_field(field_ratio);
+        }
+    }
+
+    pub fn boundaries(&self) -> Boundaries {
+        self.boundaries
+    }
+
+    /// Add a grounded plane or circle, returning its index
+    /// The whole field will be calculated again
+    pub fn add_mirror(&mut self, mirror: Mirror) -> usize {
+        self.mirrors.push(mirror);
+        let field_ratio = self.field.ratio;
+        self.reset_field(field_ratio);
+        self.mirrors.len() - 1
+    }
+
+    /// Remove the grounded plane or circle at `index`, the whole field will be calculated again
+    pub fn remove_mirror(&mut self, index: usize) -> Mirror {
+        let mirror = self.mirrors.remove(index);
+        let field_ratio = self.field.ratio;
+        self.reset_field(field_ratio);
+        mirror
+    }
+
+    pub fn mirrors(&self) -> &[Mirror] {
+        &self.mirrors
+    }
+
+    /// The index of the mirror whose surface is the closest to `position` within `distance`
+    pub fn mirror_at(&self, position: &Vector, distance: f64) -> Option<usize> {
+        let mut closest = None;
+        let mut closest_distance = distance;
+
+        for (i, mirror) in self.mirrors.iter().enumerate() {
+            let distance = mirror.distance(position);
+            if distance <= closest_distance {
+                closest = Some(i);
+                closest_distance = distance;
+            }
+        }
+
+        closest
+    }
+
+    /// Do the mirrors act on the charges?
+    /// (grounded conductors don't change the field of the currents)
+    fn uses_mirrors(&self) -> bool {
+        self.mode == Mode::Electrostatic && !self.mirrors.is_empty()
+    }
+
+    /// Is `position` inside a grounded plane or circle?
+    pub fn in_mirror(&self, position: &Vector) -> bool {
+        self.uses_mirrors() && self.mirrors.iter().any(|mirror| mirror.contains(position))
+    }
+
+    /// The image charges replacing the mirrors, without the real charges
+    pub fn image_charges(&self) -> Vec<(Vector, f64)> {
+        if !self.uses_mirrors() {
+            return Vec::new();
+        }
+
+        let mut all = Vec::new();
+        for (position, charge) in self.real_sources() {
+            if !self.in_mirror(&position) {
+                all.extend(images(&position, charge, &self.mirrors, self.law));
+            }
+        }
+
+        all
+    }
+
+    /// Change how the field is evaluated, the whole field will be calculated again
+    pub fn set_evaluator(&mut self, evaluator: Evaluator) {
+        if self.evaluator != evaluator {
+            self.evaluator = evaluator;
+            let field_ratio = self.field.ratio;
+            self.reset_field(field_ratio);
+        }
+    }
+
+    pub fn evaluator(&self) -> Evaluator {
+        self.evaluator
+    }
+
+    /// Change the method solving the conductors and dielectrics, they will be solved again
+    pub fn set_solver_method(&mut self, method: Method) {
+        self.solver.settings.method = method;
+        self.solve_pending = true;
+    }
+
+    pub fn solver_method(&self) -> Method {
+        self.solver.settings.method
+    }
+
+    /// Change the residual at which the solver stops, the conductors will be solved again
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.solver.settings.tolerance = tolerance;
+        self.solve_pending = true;
+    }
+
+    pub fn tolerance(&self) -> f64 {
+        self.solver.settings.tolerance
+    }
+
+    /// The residual left by the last solve of the conductors and dielectrics
+    pub fn residual(&self) -> f64 {
+        self.solver.residual()
+    }
+
+    /// Are there materials to solve on more than `MAX_SOLVED_CELLS` field cells?
+    /// They are left out of the field then, only the charges and their images are kept
+    pub fn too_big_to_solve(&self) -> bool {
+        if self.mode == Mode::Magnetostatic || !self.holds_materials() {
+            return false;
+        }
+        let (min_x, min_y, max_x, max_y) = self.solver_tiles();
+        let ratio = self.field.ratio as usize;
+        let cells = (max_x - min_x + 1)
+            .saturating_mul(max_y - min_y + 1)
+            .saturating_mul(ratio * ratio);
+        cells > MAX_SOLVED_CELLS
+    }
+
+    /// Empty the field grid so that it's evaluated again on the next calculation
+    fn reset_field(&mut self, field_ratio: u8) {
+        self.generation = next_generation();
+        self.materials_generation = next_generation();
+        self.field = FieldGrid::new(self.width as usize, self.height as usize, field_ratio);
+        self.free_field = self.field.clone();
+        self.induced = false;
+        self.tree = None;
+        self.sources = Arc::new(Vec::new());
+        self.mirror_potentials = Vec::new();
+
+        // Keep the solver settings, only the grid changes
+        let settings = self.solver.settings;
+        self.solver = Solver::new(field_ratio as usize);
+        self.solver.settings = settings;
+        self.solver.boundaries = self.boundaries;
+        self.solve_pending = true;
+
+        // The empty chunks are evaluated with all the charges
+        self.updated_tiles.clear();
+        self.updated_charges.clear();
+        self.dynamics.invalidate();
+    }
+
+    pub fn update_tile(&mut self, charge: i8, x: usize, y: usize) -> bool {
+        // If the tiles doesnt already have this charge
+        let old_charge = self.tiles.get(x, y);
+        if old_charge != charge {
+            self.updated_tiles.push((old_charge, x, y));
+            self.tiles.set(x, y, charge);
+
+            // A charged tile can't also be a conductor
+            if charge != 0 {
+                self.charge_index.insert((x, y));
+                if self.conductors.set(x, y, None).is_some() {
+                    self.materials_generation = next_generation();
+                }
+                self.conductor_index.remove(&(x, y));
+            } else {
+                self.charge_index.remove(&(x, y));
+            }
+            self.solve_pending = true;
+            self.dynamics.invalidate();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add a free charge, returning its index
+    pub fn add_charge(&mut self, charge: Charge) -> usize {
+        self.updated_charges.push((charge.position, charge.q));
+        self.charges.push(charge);
+        self.dynamics.invalidate();
+        self.charges.len() - 1
+    }
+
+    /// Move the free charge at `index` to `position`
+    pub fn move_charge(&mut self, index: usize, position: Vector) -> bool {
+        let charge = self.charges[index];
+        if charge.position != position {
+            self.updated_charges.push((charge.position, -charge.q));
+            self.updated_charges.push((position, charge.q));
+            self.charges[index].position = position;
+            self.dynamics.invalidate();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Remove the free charge at `index`
+    pub fn remove_charge(&mut self, index: usize) -> Charge {
+        let charge = self.charges.remove(index);
+        self.updated_charges.push((charge.position, -charge.q));
+        self.dynamics.invalidate();
+        charge
+    }
+
+    /// Pin the free charge at `index` in place, or let it move again
+    pub fn set_pinned(&mut self, index: usize, pinned: bool) {
+        self.charges[index].pinned = pinned;
+        self.charges[index].velocity = Vector::new(0.0, 0.0);
+        self.dynamics.invalidate();
+    }
+
+    /// The index of the free charge closest to `position` within `radius`
+    pub fn charge_at(&self, position: &Vector, radius: f64) -> Option<usize> {
+        let mut closest = None;
+        let mut closest_distance = radius;
+
+        for (i, charge) in self.charges.iter().enumerate() {
+            let distance = (charge.position - position).norm();
+            if distance <= closest_distance {
+                closest = Some(i);
+                closest_distance = distance;
+            }
+        }
+
+        closest
+    }
+
+    /// Make the tile a conductor at `potential`, or a normal tile when `None`
+    /// The unbounded canvas can't hold conductors, and they need line charges
+    pub fn update_conductor(&mut self, potential: Option<f64>, x: usize, y: usize) -> bool {
+        let allowed = potential.is_none() || self.law == FieldLaw::LineCharges;
+        if self.conductors.get(x, y) != potential && !self.unbounded && allowed {
+            // The conductor replaces the charge on the tile
+            if potential.is_some() {
+                self.update_tile(0, x, y);
+                self.conductor_index.insert((x, y));
+            } else {
+                self.conductor_index.remove(&(x, y));
+            }
+
+            self.conductors.set(x, y, potential);
+            self.materials_generation = next_generation();
+            self.solve_pending = true;
+            self.dynamics.invalidate();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Set the relative permittivity of the tile (1 is the vacuum)
+    /// The unbounded canvas can't hold dielectrics, and they need line charges
+    /// The permittivity must be positive, other values are refused
+    pub fn update_permittivity(&mut self, permittivity: f64, x: usize, y: usize) -> bool {
+        let valid = permittivity.is_finite() && permittivity > 0.0;
+        let allowed = valid && (permittivity == 1.0 || self.law == FieldLaw::LineCharges);
+        if self.permittivity.get(x, y) != permittivity && !self.unbounded && allowed {
+            self.permittivity.set(x, y, permittivity);
+            self.materials_generation = next_generation();
+            self.solve_pending = true;
+            self.dynamics.invalidate();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Take the pending changes and return a copy of the world holding them,
+    /// so that the field can be calculated on another thread
+    /// The calculated copy is given back with `finish_calculation`
+    pub fn start_calculation(&mut self) -> World {
+        let copy = self.clone();
+
+        self.updated_tiles.clear();
+        self.updated_charges.clear();
+        self.solve_pending = false;
+
+        copy
+    }
+
+    /// Take the field from a copy made by `start_calculation`
+    /// The field is ignored (returning false) if this world was reset in the meantime
+    pub fn finish_calculation(&mut self, calculated: World) -> bool {
+        if calculated.generation == self.generation {
+            self.field = calculated.field;
+            self.free_field = calculated.free_field;
+            self.induced = calculated.induced;
+            self.solver.take_solution(calculated.solver);
+            self.tree = calculated.tree;
+            self.sources = calculated.sources;
+            self.mirror_potentials = calculated.mirror_potentials;
+            // The capacitance stays valid while the materials don't change
+            if let Some((generation, matrix)) = calculated.capacitance {
+                if generation == self.materials_generation {
+                    self.capacitance = Some((generation, matrix));
+                }
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        if x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32 {
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The charge of the tile
+    pub fn tile(&self, x: usize, y: usize) -> i8 {
+        self.tiles.get(x, y)
+    }
+
+    /// The fixed potential of the tile, `None` when it's not a conductor
+    pub fn conductor(&self, x: usize, y: usize) -> Option<f64> {
+        self.conductors.get(x, y)
+    }
+
+    /// The relative permittivity of the tile
+    pub fn permittivity(&self, x: usize, y: usize) -> f64 {
+        self.permittivity.get(x, y)
+    }
+
+    /// The charged tiles, sorted by column
+    pub fn get_charges(&self) -> Vec<(usize, usize)> {
+        self.charge_index.iter().cloned().collect()
+    }
+
+    /// The conductor tiles, sorted by column
+    pub fn get_conductors(&self) -> Vec<(usize, usize)> {
+        self.conductor_index.iter().cloned().collect()
+    }
+
+    /// Does any tile hold a dielectric?
+    pub fn has_dielectrics(&self) -> bool {
+        !self.permittivity.is_empty()
+    }
+
+    /// Does any tile hold a conductor or a dielectric?
+    pub fn holds_materials(&self) -> bool {
+        !self.conductor_index.is_empty() || self.has_dielectrics()
+    }
+
+    /// The displacement field D = εr E at `position` (in tiles' coordinates)
+    pub fn displacement(&self, position: &Vector) -> Vector {
+        let permittivity = self.permittivity(position.x as usize, position.y as usize);
+        self.sample(position)
+            .map_or(Vector::new(0.0, 0.0), |sample| sample.field * permittivity)
+    }
+
+    /// Is the tile a charge or a conductor?
+    pub fn is_solid(&self, x: usize, y: usize) -> bool {
+        self.tiles.get(x, y) != 0 || self.conductors.get(x, y).is_some()
+    }
+
+    /// Only evaluate the field grid on the chunks seen between the corners `min` and `max`
+    /// (and on the chunks around the charges and the materials),
+    /// the field of the other chunks is evaluated where it's sampled
+    /// Returns true when other chunks are seen, so the field must be calculated again
+    pub fn set_view(&mut self, min: &Vector, max: &Vector) -> bool {
+        let (columns, rows) = self.chunk_counts();
+        if columns == 0 || rows == 0 {
+            return false;
+        }
+
+        let chunk = |x: f64, y: f64| {
+            let x = (x.max(0.0) as usize).min(self.width as usize - 1);
+            let y = (y.max(0.0) as usize).min(self.height as usize - 1);
+            chunk_of(x, y)
+        };
+        let (min_x, min_y) = chunk(min.x, min.y);
+        let (max_x, max_y) = chunk(max.x, max.y);
+
+        // Seeing the whole world is the same as seeing everything
+        let view = if min_x == 0 && min_y == 0 && max_x == columns - 1 && max_y == rows - 1 {
+            None
+        } else {
+            Some((min_x, min_y, max_x, max_y))
+        };
+
+        if self.view != view {
+            self.view = view;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The tiles of the chunks seen since `set_view` (min x, min y, max x, max y),
+    /// the whole world when everything is seen
+    pub fn seen_tiles(&self) -> (usize, usize, usize, usize) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        match self.view {
+            Some((min_x, min_y, max_x, max_y)) => (
+                min_x * CHUNK_SIZE,
+                min_y * CHUNK_SIZE,
+                ((max_x + 1) * CHUNK_SIZE).min(width) - 1,
+                ((max_y + 1) * CHUNK_SIZE).min(height) - 1,
+            ),
+            None => (0, 0, width.max(1) - 1, height.max(1) - 1),
+        }
+    }
+
+    /// The number of columns and rows of chunks covering the world
+    fn chunk_counts(&self) -> (usize, usize) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        (
+            (width + CHUNK_SIZE - 1) / CHUNK_SIZE,
+            (height + CHUNK_SIZE - 1) / CHUNK_SIZE,
+        )
+    }
+
+    /// The chunks where the field is evaluated: the chunks being viewed,
+    /// and the chunks holding charges or materials with the ones around them
+    /// The unbounded canvas has none, its field is evaluated where it's sampled
+    fn active_chunks(&self) -> BTreeSet<(usize, usize)> {
+        let (columns, rows) = self.chunk_counts();
+        let mut active = BTreeSet::new();
+        if self.unbounded {
+            return active;
+        }
+
+        let (min_x, min_y, max_x, max_y) = match self.view {
+            Some(view) => view,
+            None => {
+                for y in 0..rows {
+                    for x in 0..columns {
+                        active.insert((x, y));
+                    }
+                }
+                return active;
+            }
+        };
+        for y in min_y..max_y + 1 {
+            for x in min_x..max_x + 1 {
+                active.insert((x, y));
+            }
+        }
+
+        let mut filled: Vec<(usize, usize)> = self.charge_index
+            .iter()
+            .chain(self.conductor_index.iter())
+            .map(|&(x, y)| chunk_of(x, y))
+            .collect();
+        filled.extend(self.permittivity.chunks());
+        for charge in &self.charges {
+            let (x, y) = (charge.position.x.floor() as i32, charge.position.y.floor() as i32);
+            if self.in_bounds(x, y) {
+                filled.push(chunk_of(x as usize, y as usize));
+            }
+        }
+
+        for (cx, cy) in filled {
+            for dy in -1..2 {
+                for dx in -1..2 {
+                    let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                    if x >= 0 && y >= 0 && (x as usize) < columns && (y as usize) < rows {
+                        active.insert((x as usize, y as usize));
+                    }
+                }
+            }
+        }
+
+        active
+    }
+
+    pub fn get_borders(&self) -> Vec<(i8, usize, usize)> {
+        // A moore neighborhood
+        let directions = [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ];
+
+        let mut borders = Vec::new();
+        let mut added = HashSet::new();
+
+        for &(cx, cy) in &self.charge_index {
+            for &(dx, dy) in directions.iter() {
+                let nx = cx as i32 + dx;
+                let ny = cy as i32 + dy;
+
+                // If the neighbor is within the grid
+                if self.in_bounds(nx, ny) {
+                    // If the neighbor is not charged & we haven't already added it
+                    let border = (self.tiles.get(cx, cy), nx as usize, ny as usize);
+                    if !self.is_solid(nx as usize, ny as usize) && added.insert(border) {
+                        borders.push(border);
+                    }
+                }
+            }
+        }
+
+        borders
+    }
+
+    /// The neutral tiles next to conductors, with the direction of the field lines
+    /// (1 when the field leaves the conductor, -1 when it enters it)
+    pub fn get_conductor_borders(&self) -> Vec<(i8, usize, usize)> {
+        let directions = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+        let mut borders = Vec::new();
+        let mut added = HashSet::new();
+
+        for &(cx, cy) in &self.conductor_index {
+            for &(dx, dy) in directions.iter() {
+                let nx = cx as i32 + dx;
+                let ny = cy as i32 + dy;
+
+                if self.in_bounds(nx, ny) && !self.is_solid(nx as usize, ny as usize) {
+                    let position = Vector::new(nx as f64 + 0.5, ny as f64 + 0.5);
+                    let outward = Vector::new(dx as f64, dy as f64);
+                    let force = self
+                        .sample_with(&position, Sampling::Bilinear)
+                        .map_or(Vector::new(0.0, 0.0), |sample| sample.field);
+
+                    let sign = if force.dot(&outward) >= 0.0 { 1 } else { -1 };
+                    if added.insert((sign, nx as usize, ny as usize)) {
+                        borders.push((sign, nx as usize, ny as usize));
+                    }
+                }
+            }
+        }
+
+        borders
+    }
+
+    pub fn calculate_field(&mut self) {
+        let law = self.law;
+        let mode = self.mode;
+
+        // Every change is a charge added to the field
+        // (a removed charge is its opposite added)
+        let mut sources = Vec::new();
+        for &(old_charge, cx, cy) in &self.updated_tiles {
+            // Charge of the updated tile
+            let charge = self.tiles.get(cx, cy);
+            // The position of the updated tile
+            let position = Vector::new(cx as f64 + 0.5, cy as f64 + 0.5);
+
+            if old_charge != 0 {
+                // Remove the field that was once generated
+                sources.push((position, -old_charge as f64));
+            }
+            if charge != 0 {
+                // Add the new field of the updated charge
+                sources.push((position, charge as f64));
+            }
+        }
+        sources.extend(self.updated_charges.drain(..));
+
+        // Add the images that replace the grounded planes and circles
+        if self.uses_mirrors() {
+            sources = add_images(&sources, &self.mirrors, law);
+        }
+
+        // Add the images that satisfy the conditions on the edges
+        if !self.boundaries.is_open() {
+            let (world_width, world_height) = (self.width as f64, self.height as f64);
+            sources = sources
+                .iter()
+                .flat_map(|&(position, charge)| {
+                    self.boundaries
+                        .images(&position, charge, world_width, world_height)
+                })
+                .collect();
+        }
+
+        if !sources.is_empty() {
+            self.solve_pending = true;
+        }
+        self.sources = Arc::new(self.all_sources());
+
+        let rebuild = match self.evaluator {
+            Evaluator::BarnesHut { theta } => {
+                if !sources.is_empty() || self.tree.is_none() {
+                    self.tree = Some(QuadTree::new(&self.sources, mode, law, theta));
+                }
+                // Many changes are faster to evaluate from scratch with the tree
+                sources.len() > TREE_THRESHOLD
+            }
+            Evaluator::Direct => {
+                self.tree = None;
+                false
+            }
+        };
+        self.mirror_potentials = self.evaluate_mirror_potentials();
+        if rebuild {
+            let (width, height) = (self.width as usize, self.height as usize);
+            self.free_field = FieldGrid::new(width, height, self.free_field.ratio);
+            sources.clear();
+        }
+
+        // Forget the chunks which are not seen anymore
+        let active = self.active_chunks();
+        for chunk in self.free_field.chunks() {
+            if !active.contains(&chunk) {
+                self.free_field.free(chunk);
+                self.solve_pending = true;
+            }
+        }
+
+        if !sources.is_empty() {
+            add_sources(&mut self.free_field, sources, mode, law, &self.progress);
+        }
+
+        // The chunks which were not evaluated yet get the field of all the charges
+        let mut fresh = FieldGrid::new(
+            self.width as usize,
+            self.height as usize,
+            self.free_field.ratio,
+        );
+        for &chunk in &active {
+            if !self.free_field.has_chunk(chunk) {
+                fresh.allocate(chunk);
+            }
+        }
+        if !fresh.chunks.is_empty() {
+            self.evaluate_chunks(&mut fresh);
+            self.free_field.merge(fresh);
+            self.solve_pending = true;
+        }
+
+        // All tiles have been updated
+        self.updated_tiles.clear();
+
+        if self.solve_pending {
+            self.solve_conductors();
+            self.solve_pending = false;
+        }
+    }
+
+    /// All the charges of the world, with their images
+    pub fn all_sources(&self) -> Vec<(Vector, f64)> {
+        self.real_sources()
+            .iter()
+            .flat_map(|source| self.source_images(source))
+            .collect()
+    }
+
+    /// A `source` followed by its images in the mirrors and across the edges
+    /// (nothing for a source inside of a mirror)
+    fn source_images(&self, source: &(Vector, f64)) -> Vec<(Vector, f64)> {
+        let mut sources = vec![*source];
+
+        if self.uses_mirrors() {
+            sources = add_images(&sources, &self.mirrors, self.law);
+        }
+
+        if !self.boundaries.is_open() {
+            let (width, height) = (self.width as f64, self.height as f64);
+            sources = sources
+                .iter()
+                .flat_map(|&(position, charge)| {
+                    self.boundaries.images(&position, charge, width, height)
+                })
+                .collect();
+        }
+
+        sources
+    }
+
+    /// The charged tiles and the free charges, without any image
+    fn real_sources(&self) -> Vec<(Vector, f64)> {
+        let mut sources: Vec<(Vector, f64)> = self.get_charges()
+            .iter()
+            .map(|&(x, y)| {
+                (
+                    Vector::new(x as f64 + 0.5, y as f64 + 0.5),
+                    self.tiles.get(x, y) as f64,
+                )
+            })
+            .collect();
+        sources.extend(self.charges.iter().map(|charge| (charge.position, charge.q)));
+        sources
+    }
+
+    /// The field & potential at any `position` (in tiles' coordinates), following `sampling`
+    /// Returns `None` outside of the world
+    pub fn sample(&self, position: &Vector) -> Option<FieldSample> {
+        let sampling = self.sampling;
+        self.sample_with(position, sampling)
+    }
+
+    /// The field & potential at any `position`, sampled with `sampling`
+    pub fn sample_with(&self, position: &Vector, sampling: Sampling) -> Option<FieldSample> {
+        let mut position = *position;
+        self.wrap_position(&mut position);
+        if !self.in_bounds(position.x.floor() as i32, position.y.floor() as i32) {
+            return None;
+        }
+
+        let (field, potential) = match sampling {
+            // The canvas has no field grid, the charges are summed at the position
+            _ if self.unbounded => self.evaluate_canvas(&position),
+            Sampling::Bilinear => match self.field().interpolate(&position) {
+                Some(cell) => cell,
+                // The chunks which are not evaluated are summed at the position
+                None => {
+                    let (field, potential) = self.evaluate(&position);
+                    let (induced_field, induced_potential) = self.induced(&position);
+                    (field + induced_field, potential + induced_potential)
+                }
+            },
+            Sampling::Exact => {
+                let (field, potential) = self.evaluate_exact(&position);
+                let (induced_field, induced_potential) = self.induced(&position);
+                (field + induced_field, potential + induced_potential)
+            }
+        };
+
+        Some(FieldSample { field, potential })
+    }
+
+    /// The field & potential at every point of `viewport`, empty out of the world
+    pub fn evaluate_view(&self, viewport: Viewport) -> ViewField {
+        let mut cells = vec![(Vector::new(0.0, 0.0), 0.0); viewport.columns * viewport.rows];
+        if cells.is_empty() {
+            return ViewField { viewport, cells };
+        }
+
+        self.progress.start(viewport.rows);
+        {
+            let mut rows: Vec<&mut [(Vector, f64)]> = cells.chunks_mut(viewport.columns).collect();
+            for_each_row(&mut rows, |row, cells| {
+                for (column, cell) in cells.iter_mut().enumerate() {
+                    let position = viewport.point(column, row);
+                    if !self.unbounded {
+                        if let Some(sample) = self.sample(&position) {
+                            *cell = (sample.field, sample.potential);
+                        }
+                    } else if self.in_bounds(position.x.floor() as i32, position.y.floor() as i32)
+                    {
+                        *cell = self.evaluate_canvas(&position);
+                    }
+                }
+                self.progress.advance(1);
+            });
+        }
+
+        ViewField { viewport, cells }
+    }
+
+    /// The flux out of the closed `surface`, and the charge inside of it
+    /// The flux is of the displacement field (in magnetostatics of the magnetic field),
+    /// the parts of the surface out of the world are left out
+    /// The charge induced on the conductors is not counted in the enclosed charge
+    pub fn flux(&self, surface: &Surface) -> FluxReport {
+        let flux = gauss::flux(&surface.points(), |position| {
+            let mut position = *position;
+            self.wrap_position(&mut position);
+            let sample = self.sample(&position)?;
+
+            match self.mode {
+                Mode::Electrostatic => {
+                    let (x, y) = (position.x as usize, position.y as usize);
+                    Some(sample.field * self.permittivity.get(x, y))
+                }
+                Mode::Magnetostatic => Some(sample.field),
+            }
+        });
+
+        let enclosed = self.real_sources()
+            .iter()
+            .filter(|&&(position, _)| surface.contains(&position))
+            .fold(0.0, |total, &(_, charge)| total + charge);
+
+        // The field of point charges in a plane spreads in three dimensions,
+        // so the flux through a curve of the plane doesn't only depend on the enclosed charge
+        let expected = match (self.mode, self.law) {
+            (Mode::Electrostatic, FieldLaw::LineCharges) => Some(2.0 * f64::consts::PI * enclosed),
+            (Mode::Electrostatic, FieldLaw::PointCharges) => None,
+            // There are no magnetic charges
+            (Mode::Magnetostatic, _) => Some(0.0),
+        };
+
+        FluxReport {
+            flux,
+            enclosed,
+            expected,
+        }
+    }
+
+    /// The integral of the field along the path through `points`,
+    /// and the potential difference between its ends
+    /// The parts of the path out of the world are left out
+    pub fn line_integral(&self, points: &[Vector]) -> LineIntegral {
+        let (integral, length) =
+            integrate(points, |position| self.sample(position).map(|sample| sample.field));
+
+        let voltage = match (points.first(), points.last()) {
+            (Some(start), Some(end)) => match (self.sample(start), self.sample(end)) {
+                (Some(start), Some(end)) => Some(start.potential - end.potential),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        LineIntegral {
+            integral,
+            voltage,
+            length,
+        }
+    }
+
+    /// The field & potential of the charges alone at any `position`
+    /// Uses the tree when evaluating with Barnes–Hut, otherwise sums every charge
+    pub fn evaluate(&self, position: &Vector) -> (Vector, f64) {
+        match self.tree {
+            Some(ref tree) => tree.evaluate(position),
+            None => self.evaluate_exact(position),
+        }
+    }
+
+    /// The field & potential added to the field of the charges at `position` by the conductors,
+    /// the dielectrics and the mirrors, from the field grids
+    /// Where the field grids are not allocated it comes from the solution of the solver
+    fn induced(&self, position: &Vector) -> (Vector, f64) {
+        let total = self.field().interpolate(position);
+        let free = self.free_field.interpolate(position);
+        if let (Some((total_field, total_potential)), Some((free_field, free_potential))) =
+            (total, free)
+        {
+            return (total_field - free_field, total_potential - free_potential);
+        }
+
+        // Conductors and mirrors hold their potential without any field
+        let (x, y) = (position.x.floor() as usize, position.y.floor() as usize);
+        let mut fixed = self.conductors.get(x, y);
+        for (mirror, &potential) in self.mirrors.iter().zip(&self.mirror_potentials) {
+            if mirror.contains(position) {
+                fixed = Some(potential);
+            }
+        }
+        if let Some(potential) = fixed {
+            let (free_field, free_potential) = self.evaluate(position);
+            return (-free_field, potential - free_potential);
+        }
+
+        let ratio = self.field.ratio as f64;
+        let (x, y) = ((position.x * ratio) as i32, (position.y * ratio) as i32);
+        self.solver.correction_field(x, y)
+    }
+
+    /// The potential of everything and of the charges alone on the field cell (`x`, `y`),
+    /// evaluated on the cell when its chunk is not allocated
+    fn cell_potentials(&self, x: usize, y: usize) -> (f64, f64) {
+        match (self.field().cell(x, y), self.free_field.cell(x, y)) {
+            (Some((_, total)), Some((_, free))) => (total, free),
+            _ => {
+                let ratio = self.field.ratio as f64;
+                let position = Vector::new(x as f64 + 0.5, y as f64 + 0.5) / ratio;
+                let free = self.evaluate(&position).1;
+                (free + self.induced(&position).1, free)
+            }
+        }
+    }
+
+    /// The field & potential on the canvas at `position`, from the charges of the last
+    /// calculation (with the tree when evaluating with Barnes–Hut)
+    /// Everything inside of a mirror is at the potential of its surface
+    fn evaluate_canvas(&self, position: &Vector) -> (Vector, f64) {
+        for (mirror, &potential) in self.mirrors.iter().zip(&self.mirror_potentials) {
+            if mirror.contains(position) {
+                return (Vector::new(0.0, 0.0), potential);
+            }
+        }
+
+        self.evaluate(position)
+    }
+
+    /// The field & potential of the charges alone at `position`, summing every charge
+    /// as it was when the field was last calculated
+    pub fn evaluate_exact(&self, position: &Vector) -> (Vector, f64) {
+        self.sum_sources(&self.sources, position)
+    }
+
+    /// The field & potential of `sources` at `position`
+    fn sum_sources(&self, sources: &[(Vector, f64)], position: &Vector) -> (Vector, f64) {
+        let mut field = Vector::new(0.0, 0.0);
+        let mut potential = 0.0;
+
+        for &(source, charge) in sources {
+            let (source_field, source_potential) =
+                source_field(self.mode, self.law, charge, &(position - source));
+            field += source_field;
+            potential += source_potential;
+        }
+
+        (field, potential)
+    }
+
+    /// The force on each free charge from all the other charges, the images,
+    /// the conductors and the dielectrics
+    /// The charges are softened (see `Dynamics`) so close charges don't have huge forces,
+    /// and charges right on top of each other don't push each other
+    pub fn forces(&self) -> Vec<Vector> {
+        let softening = self.dynamics.softening;
+        let real_sources = self.real_sources();
+        let first_charge = real_sources.len() - self.charges.len();
+        // Every real source with its images, the source first
+        let sources: Vec<Vec<(Vector, f64)>> = real_sources
+            .iter()
+            .map(|source| self.source_images(source))
+            .collect();
+
+        self.charges
+            .iter()
+            .enumerate()
+            .map(|(i, charge)| {
+                let mut field = Vector::new(0.0, 0.0);
+                for (index, images) in sources.iter().enumerate() {
+                    // A charge doesn't push itself, but its images push it
+                    let skip = if index == first_charge + i { 1 } else { 0 };
+                    for &(position, q) in images.iter().skip(skip) {
+                        let delta = charge.position - position;
+                        // Overlapping charges have no direction between them,
+                        // and the softened force vanishes there
+                        if delta.x == 0.0 && delta.y == 0.0 {
+                            continue;
+                        }
+                        field += softened_field(self.mode, self.law, q, &delta, softening);
+                    }
+                }
+
+                // The field of the conductors and dielectrics, from the last calculated field
+                let (x, y) = (charge.position.x, charge.position.y);
+                if self.in_bounds(x.floor() as i32, y.floor() as i32) {
+                    field += self.induced(&charge.position).0;
+                }
+
+                get_force(self.mode, charge.q, &field)
+            })
+            .collect()
+    }
+
+    /// The field & potential at the `position` of a charge, from all the `sources`
+    /// (the charge itself adds nothing) and from the conductors and the dielectrics
+    fn interaction(&self, position: &Vector, sources: &[(Vector, f64)]) -> (Vector, f64) {
+        let mut field = Vector::new(0.0, 0.0);
+        let mut potential = 0.0;
+        for &(source, q) in sources {
+            let (source_field, source_potential) =
+                source_field(self.mode, self.law, q, &(position - source));
+            field += source_field;
+            potential += source_potential;
+        }
+
+        // The field of the conductors and dielectrics, from the last calculated field
+        if self.in_bounds(position.x.floor() as i32, position.y.floor() as i32) {
+            let (induced_field, induced_potential) = self.induced(position);
+            field += induced_field;
+            potential += induced_potential;
+        }
+
+        (field, potential)
+    }
+
+    /// The electrostatic energy of the charges, ½ Σ qV, where V is the potential of all the
+    /// other charges, the images, the conductors and the dielectrics
+    /// (in magnetostatics V is the vector potential)
+    pub fn energy(&self) -> f64 {
+        let sources = self.all_sources();
+        self.real_sources()
+            .iter()
+            .map(|&(position, q)| q * self.interaction(&position, &sources).1)
+            .sum::<f64>()
+            / 2.0
+    }
+
+    /// The net force on every charged tile, (x, y, force)
+    pub fn tile_forces(&self) -> Vec<(usize, usize, Vector)> {
+        let sources = self.all_sources();
+        self.get_charges()
+            .iter()
+            .map(|&(x, y)| {
+                let position = Vector::new(x as f64 + 0.5, y as f64 + 0.5);
+                let field = self.interaction(&position, &sources).0;
+                (x, y, get_force(self.mode, self.tiles.get(x, y) as f64, &field))
+            })
+            .collect()
+    }
+
+    /// The net force on every connected region of charged tiles and on every free charge
+    /// (the forces between the tiles of a region cancel out)
+    /// Unlike `forces` the charges are not softened
+    pub fn region_forces(&self) -> Vec<(ChargeRegion, Vector)> {
+        let (regions, region_map) = self.charge_regions();
+        let mut forces = vec![Vector::new(0.0, 0.0); regions.len()];
+
+        for (x, y, force) in self.tile_forces() {
+            if let Some(&region) = region_map.get(&(x, y)) {
+                forces[region] += force;
+            }
+        }
+
+        let sources = self.all_sources();
+        let tile_regions = regions.len() - self.charges.len();
+        for (i, charge) in self.charges.iter().enumerate() {
+            let field = self.interaction(&charge.position, &sources).0;
+            forces[tile_regions + i] = get_force(self.mode, charge.q, &field);
+        }
+
+        regions.into_iter().zip(forces).collect()
+    }
+
+    /// The acceleration of each free charge, zero for the pinned ones
+    fn accelerations(&self) -> Vec<Vector> {
+        self.forces()
+            .iter()
+            .zip(&self.charges)
+            .map(|(force, charge)| {
+                if charge.pinned {
+                    Vector::new(0.0, 0.0)
+                } else {
+                    force / charge.mass
+                }
+            })
+            .collect()
+    }
+
+    /// Move the free charges for one time step with the velocity Verlet method
+    /// The charges bounce on the edges, the charged tiles, the conductors and the mirrors
+    /// Returns true when any charge moved
+    pub fn step(&mut self) -> bool {
+        let time_step = self.dynamics.time_step;
+        let accelerations = match self.dynamics.accelerations(self.charges.len()) {
+            Some(accelerations) => accelerations.to_vec(),
+            None => self.accelerations(),
+        };
+
+        let mut moved = false;
+        for (i, acceleration) in accelerations.iter().enumerate() {
+            let charge = self.charges[i];
+            if charge.pinned {
+                continue;
+            }
+
+            let mut position = charge.position
+                + charge.velocity * time_step
+                + acceleration * time_step.powi(2) / 2.0;
+            self.wrap_position(&mut position);
+
+            let (x, y) = (position.x.floor() as i32, position.y.floor() as i32);
+            if !self.in_bounds(x, y)
+                || self.is_solid(x as usize, y as usize)
+                || self.in_mirror(&position)
+            {
+                // Bounce back, the charge stays where it was
+                self.charges[i].velocity = -charge.velocity;
+            } else if self.move_charge(i, position) {
+                moved = true;
+            }
+        }
+        // Moving the charges forgot the accelerations, the new ones are kept below
+
+        // Update the velocities with the mean of the old and new accelerations
+        let new_accelerations = self.accelerations();
+        for (charge, (old, new)) in self
+            .charges
+            .iter_mut()
+            .zip(accelerations.iter().zip(&new_accelerations))
+        {
+            charge.velocity += (old + new) * time_step / 2.0;
+        }
+
+        self.dynamics.set_accelerations(new_accelerations);
+        self.dynamics.time += time_step;
+        moved
+    }
+
+    /// Compare the field of the charges shown, evaluated with the Barnes–Hut tree,
+    /// with the exact sum on about `samples` points
+    /// On a bounded world the points are the cells of the field grid, which also hold
+    /// the charges added exactly since the tree was built
+    /// Returns `None` when the world is not evaluated with Barnes–Hut,
+    /// or when the canvas has no charges
+    pub fn tree_error(&self, samples: usize) -> Option<ErrorReport> {
+        let tree = match self.tree {
+            Some(ref tree) => tree,
+            None => return None,
+        };
+
+        // (position, field shown)
+        let mut compared = Vec::new();
+        if self.unbounded {
+            // The canvas is measured around its charges
+            let margin = Vector::new(CHUNK_SIZE as f64, CHUNK_SIZE as f64);
+            let sources = self.real_sources();
+            let first = match sources.first() {
+                Some(&(position, _)) => position,
+                None => return None,
+            };
+            let (min, max) = sources.iter().fold(
+                (first, first),
+                |(min, max), &(position, _)| {
+                    (
+                        Vector::new(min.x.min(position.x), min.y.min(position.y)),
+                        Vector::new(max.x.max(position.x), max.y.max(position.y)),
+                    )
+                },
+            );
+            let (origin, size) = (min - margin, max - min + margin * 2.0);
+
+            let ratio = self.field.ratio as f64;
+            let (width, height) = ((size.x * ratio) as usize, (size.y * ratio) as usize);
+            let stride = ((width * height / samples.max(1)) as f64).sqrt().max(1.0) as usize;
+            for y in (0..height).step_by(stride) {
+                for x in (0..width).step_by(stride) {
+                    let position = origin + Vector::new(x as f64 + 0.5, y as f64 + 0.5) / ratio;
+                    compared.push((position, tree.evaluate(&position).0));
+                }
+            }
+        } else {
+            let grid = &self.free_field;
+            let ratio = grid.ratio as f64;
+            let side = grid.side();
+            let cells = grid.allocated_cells();
+            let stride = ((cells / samples.max(1)) as f64).sqrt().max(1.0) as usize;
+            for (cx, cy) in grid.chunks() {
+                let x_end = ((cx + 1) * side).min(grid.width);
+                let y_end = ((cy + 1) * side).min(grid.height);
+                for y in (cy * side..y_end).step_by(stride) {
+                    for x in (cx * side..x_end).step_by(stride) {
+                        let position = Vector::new(x as f64 + 0.5, y as f64 + 0.5) / ratio;
+                        if let Some((field, _)) = grid.cell(x, y) {
+                            compared.push((position, field));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut report = ErrorReport {
+            max_error: 0.0,
+            mean_error: 0.0,
+            samples: 0,
+        };
+        for (position, shown) in compared {
+            let exact = self.evaluate_exact(&position).0;
+            // The field of a charge is not a number on its own center
+            if exact.norm() == 0.0 || !exact.norm().is_finite() {
+                continue;
+            }
+
+            let error = (shown - exact).norm() / exact.norm();
+            report.max_error = report.max_error.max(error);
+            report.mean_error += error;
+            report.samples += 1;
+        }
+        if report.samples > 0 {
+            report.mean_error /= report.samples as f64;
+        }
+
+        Some(report)
+    }
+
+    /// Evaluate the field of all the charges on the allocated chunks of `grid`,
+    /// with the tree when using the Barnes–Hut evaluator
+    fn evaluate_chunks(&self, grid: &mut FieldGrid) {
+        let ratio = grid.ratio as f64;
+        match self.tree {
+            Some(ref tree) => grid.for_each_cell(&self.progress, |x, y, cell| {
+                let position = Vector::new(x as f64 + 0.5, y as f64 + 0.5) / ratio;
+                *cell = tree.evaluate(&position);
+            }),
+            None => add_sources(
+                grid,
+                self.sources.to_vec(),
+                self.mode,
+                self.law,
+                &self.progress,
+            ),
+        }
+    }
+
+    /// The potential on the surface of each mirror, held by everything inside of it
+    fn evaluate_mirror_potentials(&self) -> Vec<f64> {
+        if self.uses_mirrors() {
+            self.mirrors
+                .iter()
+                .map(|mirror| self.evaluate(&mirror.surface_point()).1)
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Add the field induced by the conductors and dielectrics to the field of the charges
+    fn solve_conductors(&mut self) {
+        let mirror_potentials = self.mirror_potentials.clone();
+
+        // Conductors and dielectrics don't change the field of the currents
+        let solved =
+            self.mode == Mode::Electrostatic && self.holds_materials() && !self.too_big_to_solve();
+        if !solved {
+            self.solver.reset();
+        }
+
+        // Without anything induced the field of the charges is read instead
+        self.induced = solved || self.uses_mirrors();
+        if !self.induced {
+            let ratio = self.field.ratio;
+            self.field = FieldGrid::new(self.width as usize, self.height as usize, ratio);
+            return;
+        }
+
+        self.field = self.free_field.clone();
+        ground_mirrors(&mut self.field, &self.mirrors, &mirror_potentials, &self.progress);
+        if !solved {
+            return;
+        }
+
+        // The tiles inside of the mirrors are conductors for the solver,
+        // so the correction vanishes on the mirrors and the images stay exact
+        let tiles = self.solver_tiles();
+        let (min_x, min_y, max_x, max_y) = tiles;
+        let mut conductors = self.conductors.clone();
+        for y in min_y..max_y + 1 {
+            for x in min_x..max_x + 1 {
+                let center = Vector::new(x as f64 + 0.5, y as f64 + 0.5);
+                for (mirror, &potential) in self.mirrors.iter().zip(&mirror_potentials) {
+                    if mirror.contains(&center) {
+                        conductors.set(x, y, Some(potential));
+                    }
+                }
+            }
+        }
+
+        // The potential of the charges on the cells which are not evaluated is summed there
+        let mut solver = mem::replace(&mut self.solver, Solver::new(1));
+        {
+            let ratio = self.field.ratio as f64;
+            let free = |x: usize, y: usize| match self.field.cell(x, y) {
+                Some((_, potential)) => potential,
+                None => {
+                    self.evaluate(&(Vector::new(x as f64 + 0.5, y as f64 + 0.5) / ratio))
+                        .1
+                }
+            };
+            solver.solve(&conductors, &self.permittivity, free, tiles, &self.progress);
+        }
+        self.solver = solver;
+
+        let ratio = self.field.ratio as usize;
+        let solver = &self.solver;
+        self.field.for_each_cell(&self.progress, |x, y, cell| {
+            if let Some(potential) = conductors.get(x / ratio, y / ratio) {
+                // There is no field inside of a conductor
+                *cell = (Vector::new(0.0, 0.0), potential);
+            } else {
+                let (field, potential) = solver.correction_field(x as i32, y as i32);
+                cell.0 += field;
+                cell.1 += potential;
+            }
+        });
+
+        ground_mirrors(&mut self.field, &self.mirrors, &mirror_potentials, &self.progress);
+    }
+
+    /// The tiles covered by the solver (min x, min y, max x, max y)
+    /// With open edges the correction past the box around the conductors, the dielectrics
+    /// and the circles is the one of their charge seen from far away,
+    /// so only this box and its surroundings are solved
+    fn solver_tiles(&self) -> (usize, usize, usize, usize) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let whole = (0, 0, width - 1, height - 1);
+        // The planes ground the field up to the edges
+        let planes = self.mirrors.iter().any(|mirror| match *mirror {
+            Mirror::Plane { .. } => true,
+            Mirror::Circle { .. } => false,
+        });
+        if !self.boundaries.is_open() || planes {
+            return whole;
+        }
+
+        let mut boxes: Vec<(Vector, Vector)> = self
+            .conductor_index
+            .iter()
+            .map(|&(x, y)| {
+                let corner = Vector::new(x as f64, y as f64);
+                (corner, corner + Vector::new(1.0, 1.0))
+            })
+            .collect();
+        let chunk = CHUNK_SIZE as f64;
+        for (x, y) in self.permittivity.chunks() {
+            let corner = Vector::new(x as f64, y as f64) * chunk;
+            boxes.push((corner, corner + Vector::new(chunk, chunk)));
+        }
+        for mirror in &self.mirrors {
+            if let Mirror::Circle { center, radius } = *mirror {
+                let radius = Vector::new(radius, radius);
+                boxes.push((center - radius, center + radius));
+            }
+        }
+        if boxes.is_empty() {
+            return whole;
+        }
+
+        let infinity = Vector::new(f64::INFINITY, f64::INFINITY);
+        let (min, max) = boxes.iter().fold((infinity, -infinity), |(min, max), &(low, high)| {
+            (
+                Vector::new(min.x.min(low.x), min.y.min(low.y)),
+                Vector::new(max.x.max(high.x), max.y.max(high.y)),
+            )
+        });
+
+        let size = max - min;
+        let margin = size.x.max(size.y) / 2.0 + SOLVER_MARGIN;
+        // The first and last tiles along an axis, the multigrid coarsens best
+        // when the box is a multiple of `SOLVER_BLOCK` tiles
+        let axis = |min: f64, max: f64, length: usize| {
+            let first = (min - margin).floor().max(0.0) as usize;
+            let end = (max + margin).ceil() as usize;
+            let size = (end.saturating_sub(first) / SOLVER_BLOCK + 1) * SOLVER_BLOCK;
+            if size >= length {
+                (0, length - 1)
+            } else {
+                let first = first.min(length - size);
+                (first, first + size - 1)
+            }
+        };
+        let (min_x, max_x) = axis(min.x, max.x, width);
+        let (min_y, max_y) = axis(min.y, max.y, height);
+        (min_x, min_y, max_x, max_y)
+    }
+
+    /// The charge induced on every conductor tile (zero on the other tiles)
+    /// It's the flux of the displacement field leaving the tile divided by 2π
+    /// (Gauss's law for line charges, which the solver follows),
+    /// without the flux of the charges in the vacuum
+    pub fn surface_charges(&self) -> Chunks<f64> {
+        let mut charges = Chunks::new(self.width as usize, self.height as usize, 0.0);
+        if self.mode == Mode::Magnetostatic {
+            return charges;
+        }
+
+        let ratio = self.field.ratio as usize;
+        let (width, height) = (self.field.width, self.field.height);
+        let conductor = |x: usize, y: usize| self.conductors.get(x / ratio, y / ratio);
+
+        for &(tile_x, tile_y) in &self.conductor_index {
+            let potential = self.conductors.get(tile_x, tile_y);
+            let mut charge = 0.0;
+            for y in tile_y * ratio..(tile_y + 1) * ratio {
+                for x in tile_x * ratio..(tile_x + 1) * ratio {
+                    // The flux through the sides of the cell touching the free space,
+                    // or another conductor held at a different potential
+                    let neighbors = [
+                        (x.wrapping_sub(1), y),
+                        (x + 1, y),
+                        (x, y.wrapping_sub(1)),
+                        (x, y + 1),
+                    ];
+                    for &(nx, ny) in neighbors.iter() {
+                        if nx >= width || ny >= height || conductor(nx, ny) == potential {
+                            continue;
+                        }
+
+                        let permittivity = self.permittivity.get(nx / ratio, ny / ratio);
+                        let (total, free) = self.cell_potentials(x, y);
+                        let (neighbor_total, neighbor_free) = self.cell_potentials(nx, ny);
+                        let (total, free) = (total - neighbor_total, free - neighbor_free);
+                        charge += (permittivity * total - free) / (2.0 * f64::consts::PI);
+                    }
+                }
+            }
+            charges.set(tile_x, tile_y, charge);
+        }
+
+        charges
+    }
+
+    /// The conductors of the world, with the charge induced on them
+    pub fn conductor_bodies(&self) -> Vec<ConductorBody> {
+        let surface = self.surface_charges();
+
+        conductor_groups(&self.conductor_index, &self.conductors)
+            .into_iter()
+            .map(|tiles| {
+                let (x, y) = tiles[0];
+                let charge = tiles.iter().map(|&(x, y)| surface.get(x, y)).sum();
+                ConductorBody {
+                    potential: self.conductors.get(x, y).unwrap_or(0.0),
+                    charge,
+                    tiles,
+                }
+            })
+            .collect()
+    }
+
+    /// The capacitance matrix of the conductors, in the order of `conductor_bodies`
+    /// The element (i, j) is the charge induced on the conductor i when the conductor j
+    /// is held at a unit potential and all the others are grounded, without any charge
+    /// Every conductor takes a solution of the field,
+    /// so the matrix is kept until the conductors, the dielectrics or the field are reset
+    /// It's empty when the world is too big to solve
+    pub fn capacitance(&mut self) -> Vec<Vec<f64>> {
+        if let Some((generation, ref matrix)) = self.capacitance {
+            if generation == self.materials_generation {
+                return matrix.clone();
+            }
+        }
+
+        if self.too_big_to_solve() {
+            return Vec::new();
+        }
+        let groups = conductor_groups(&self.conductor_index, &self.conductors);
+
+        // The same materials and edges, without the charges,
+        // with its own progress so the progress of the field isn't started again
+        let mut world = self.clone();
+        world.progress = Arc::new(Progress::new());
+        world.tiles.clear();
+        world.charge_index.clear();
+        world.charges.clear();
+        world.mode = Mode::Electrostatic;
+        // The small charges of the grounded conductors need a precise solution
+        let tolerance = world.tolerance().min(CAPACITANCE_TOLERANCE);
+        world.set_tolerance(tolerance);
+        let ratio = world.field.ratio;
+        world.reset_field(ratio);
+
+        let mut matrix = vec![vec![0.0; groups.len()]; groups.len()];
+        for j in 0..groups.len() {
+            for (i, group) in groups.iter().enumerate() {
+                let potential = if i == j { 1.0 } else { 0.0 };
+                for &(x, y) in group {
+                    world.conductors.set(x, y, Some(potential));
+                }
+            }
+            world.solve_pending = true;
+            world.calculate_field();
+
+            let surface = world.surface_charges();
+            for (i, group) in groups.iter().enumerate() {
+                matrix[i][j] = group.iter().map(|&(x, y)| surface.get(x, y)).sum();
+            }
+        }
+
+        self.capacitance = Some((self.materials_generation, matrix.clone()));
+        matrix
+    }
+
+    /// Trace the field lines from the charges and the conductors, following `line_settings`
+    /// Every region of charge emits lines in proportion to its charge (up to `max_lines` for all),
+    /// evenly spaced along its edge,
+    /// the negative charges only emit the lines that don't come from the positive ones
+    pub fn calculate_lines(&mut self) -> Vec<Vec<Vector>> {
+        if self.mode == Mode::Magnetostatic {
+            return self.calculate_loops();
+        }
+
+        let use_tree = self.use_tree_for_lines();
+        let (regions, region_map) = self.charge_regions();
+        let tile_regions = regions.len() - self.charges.len();
+        // The region of charge at `position`, if any
+        let region_at = |position: &Vector| {
+            let (x, y) = (position.x.floor() as i32, position.y.floor() as i32);
+            if !self.in_bounds(x, y) {
+                return None;
+            }
+            region_map
+                .get(&(x as usize, y as usize))
+                .cloned()
+                .or_else(|| {
+                    self.charge_at(position, CHARGE_RADIUS)
+                        .map(|index| tile_regions + index)
+                })
+        };
+
+        // Where a line of a region can start: free, and out of the region
+        let can_start = |seed: &Vector, index: usize| {
+            self.in_bounds(seed.x.floor() as i32, seed.y.floor() as i32)
+                && region_at(seed) != Some(index)
+                && self.is_free(seed)
+        };
+
+        let mut region_tiles = vec![Vec::new(); tile_regions];
+        for (&tile, &index) in &region_map {
+            region_tiles[index].push(tile);
+        }
+
+        // The charge of a line grows when the regions would emit too many lines
+        let total: f64 = regions.iter().map(|region| region.charge.abs()).sum();
+        let settings = self.line_settings;
+        let charge_per_line = settings
+            .charge_per_line
+            .max(total / settings.max_lines as f64);
+
+        // The starts of the lines of every region, the lines reaching it are taken away
+        let mut seeds: Vec<Vec<Vector>> = regions
+            .iter()
+            .enumerate()
+            .map(|(index, region)| {
+                // A small charge still emits a line
+                let count = (region.charge.abs() / charge_per_line).round().max(1.0) as usize;
+                if index < tile_regions {
+                    region_tiles[index].sort();
+                    let sides = self.region_sides(&region_tiles[index], index, &region_map);
+                    even_points(&sides, count, SEED_STEP)
+                } else {
+                    even_angles(count)
+                        .iter()
+                        .map(|angle| {
+                            let direction = Vector::new(angle.cos(), angle.sin());
+                            region.center + direction * (CHARGE_RADIUS + SEED_STEP)
+                        })
+                        .collect()
+                }
+            })
+            .collect();
+
+        let mut lines = Vec::new();
+        let mut starts = Vec::new();
+        for (index, region) in regions.iter().enumerate() {
+            if region.charge > 0.0 {
+                starts.extend(seeds[index].iter().filter(|seed| can_start(seed, index)));
+            }
+        }
+
+        // The conductors emit the lines leaving them, the others are traced backwards
+        let borders = self.get_conductor_borders();
+        let (outgoing, incoming): (Vec<_>, Vec<_>) =
+            borders.iter().partition(|&&(sign, _, _)| sign > 0);
+        starts.extend(
+            outgoing
+                .iter()
+                .map(|&&(_, x, y)| Vector::new(x as f64 + 0.5, y as f64 + 0.5)),
+        );
+
+        for start in starts {
+            let (segments, end) = self.trace_line(start, 1.0, use_tree);
+            lines.extend(segments);
+
+            // The line reaching a negative region is one less line for it to emit
+            if let Some(end) = end {
+                if let Some(target) = region_at(&end) {
+                    if regions[target].charge < 0.0 {
+                        claim_point(&mut seeds[target], &end);
+                    }
+                }
+            }
+        }
+
+        for &&(_, x, y) in &incoming {
+            let start = Vector::new(x as f64 + 0.5, y as f64 + 0.5);
+            let (segments, end) = self.trace_line(start, -1.0, use_tree);
+
+            // A line between a conductor and a charge (or another conductor) is already traced
+            if let Some(end) = end {
+                let (x, y) = (end.x.floor() as usize, end.y.floor() as usize);
+                if !self.in_mirror(&end)
+                    && (self.is_solid(x, y) || self.charge_at(&end, CHARGE_RADIUS).is_some())
+                {
+                    continue;
+                }
+            }
+            lines.extend(segments);
+        }
+
+        // The negative regions emit the lines which were not reached
+        for (index, region) in regions.iter().enumerate() {
+            if region.charge < 0.0 {
+                for seed in seeds[index].iter().filter(|seed| can_start(seed, index)) {
+                    lines.extend(self.trace_line(*seed, -1.0, use_tree).0);
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// The connected regions of charged tiles with the same sign, followed by the free charges,
+    /// and the region of every tile
+    fn charge_regions(&self) -> (Vec<ChargeRegion>, HashMap<(usize, usize), usize>) {
+        let mut region_map = HashMap::new();
+        let mut regions = Vec::new();
+
+        for &(x, y) in &self.charge_index {
+            if region_map.contains_key(&(x, y)) {
+                continue;
+            }
+
+            let index = regions.len();
+            let sign = self.tiles.get(x, y).signum();
+            let mut charge = 0.0;
+            let mut moment = Vector::new(0.0, 0.0);
+
+            // Flood the tiles touching the region, even on a corner
+            let mut stack = vec![(x, y)];
+            region_map.insert((x, y), index);
+            while let Some((x, y)) = stack.pop() {
+                let q = self.tiles.get(x, y) as f64;
+                charge += q;
+                moment += Vector::new(x as f64 + 0.5, y as f64 + 0.5) * q;
+
+                for dy in -1..2 {
+                    for dx in -1..2 {
+                        let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                        if self.in_bounds(nx, ny) {
+                            let (nx, ny) = (nx as usize, ny as usize);
+                            if !region_map.contains_key(&(nx, ny))
+                                && self.tiles.get(nx, ny).signum() == sign
+                            {
+                                region_map.insert((nx, ny), index);
+                                stack.push((nx, ny));
+                            }
+                        }
+                    }
+                }
+            }
+
+            regions.push(ChargeRegion {
+                center: moment / charge,
+                charge,
+            });
+        }
+
+        regions.extend(self.charges.iter().map(|charge| ChargeRegion {
+            center: charge.position,
+            charge: charge.q,
+        }));
+
+        (regions, region_map)
+    }
+
+    /// The sides of the `tiles` of the region of charge `index` facing out of it,
+    /// following each other in loops around it (with the region on their left)
+    fn region_sides(
+        &self,
+        tiles: &[(usize, usize)],
+        index: usize,
+        region_map: &HashMap<(usize, usize), usize>,
+    ) -> Vec<(Vector, Vector)> {
+        let inside = |x: i32, y: i32| {
+            self.in_bounds(x, y) && region_map.get(&(x as usize, y as usize)) == Some(&index)
+        };
+
+        // The ends of the sides starting at every corner
+        let mut ends: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+        let mut corners = Vec::new();
+        for &(x, y) in tiles {
+            let (x, y) = (x as i32, y as i32);
+            let around = [
+                ((0, -1), (x, y), (x + 1, y)),
+                ((1, 0), (x + 1, y), (x + 1, y + 1)),
+                ((0, 1), (x + 1, y + 1), (x, y + 1)),
+                ((-1, 0), (x, y + 1), (x, y)),
+            ];
+            for &((dx, dy), start, end) in &around {
+                if !inside(x + dx, y + dy) {
+                    ends.entry(start).or_insert_with(Vec::new).push(end);
+                    corners.push(start);
+                }
+            }
+        }
+
+        // Follow the sides from corner to corner until they close
+        let point = |(x, y): (i32, i32)| Vector::new(x as f64, y as f64);
+        let mut sides = Vec::new();
+        for mut corner in corners {
+            while let Some(end) = ends.get_mut(&corner).and_then(|ends| ends.pop()) {
+                sides.push((point(corner), point(end)));
+                corner = end;
+            }
+        }
+
+        sides
+    }
+
+    /// Trace a field line from `start`, backwards when `sign` is negative
+    /// Returns the pieces of the line split by the periodic edges,
+    /// and where the line ended in a charge, a conductor or a mirror
+    fn trace_line(
+        &self,
+        start: Vector,
+        sign: f64,
+        use_tree: bool,
+    ) -> (Vec<Vec<Vector>>, Option<Vector>) {
+        let settings = self.line_settings;
+
+        // The lines can't leave the world, and can't go where there is no field
+        let direction = |position: &Vector| {
+            let mut position = *position;
+            self.wrap_position(&mut position);
+            if !self.in_bounds(position.x.floor() as i32, position.y.floor() as i32) {
+                return None;
+            }
+
+            let force = self.line_force(&position, use_tree);
+            let norm = force.norm();
+            if norm == 0.0 || !norm.is_finite() {
+                None
+            } else {
+                Some(force * sign / norm)
+            }
+        };
+        // The lines end on the charges, the conductors and the grounded mirrors
+        let blocked = |position: &Vector| {
+            let mut position = *position;
+            self.wrap_position(&mut position);
+            !self.is_free(&position)
+        };
+
+        let mut segments = Vec::new();
+        let mut line = vec![start];
+        let mut position = start;
+        let mut old_direction = Vector::new(0.0, 0.0);
+        let mut step_length = settings.max_step;
+        let mut end = None;
+
+        let mut length = 0.0;
+        while length < settings.max_length {
+            let step = match settings.step(&position, step_length, &direction) {
+                Some(step) => step,
+                None => break,
+            };
+
+            if let Some(mut hit) = first_blocked(&position, &step.position, &blocked) {
+                line.push(hit);
+                self.wrap_position(&mut hit);
+                end = Some(hit);
+                break;
+            }
+
+            // The line turned back, it reached a point without field
+            if step.direction.dot(&old_direction) < 0.0 {
+                break;
+            }
+
+            old_direction = step.direction;
+            step_length = step.next;
+            length += step.length;
+            position = step.position;
+            line.push(position);
+
+            // Past a periodic edge the line continues on the other side
+            if self.wrap_position(&mut position) {
+                segments.push(line);
+                line = vec![position];
+
+                // Lines can't wrap around the world forever
+                if segments.len() > MAX_WRAPS {
+                    break;
+                }
+            }
+        }
+
+        segments.push(line);
+        (segments, end)
+    }
+
+    /// Can the lines be traced through the Barnes–Hut tree instead of the field grid?
+    /// (the tree doesn't know about conductors and dielectrics)
+    fn use_tree_for_lines(&self) -> bool {
+        self.tree.is_some() && self.conductor_index.is_empty() && !self.has_dielectrics()
+    }
+
+    #[inline]
+    /// The field used to trace the lines at `position`
+    /// (sampled between the field tiles, so the lines are smooth)
+    fn line_force(&self, position: &Vector, use_tree: bool) -> Vector {
+        match self.tree {
+            Some(ref tree) if use_tree => tree.evaluate(position).0,
+            _ => self
+                .sample(position)
+                .map(|sample| sample.field)
+                .unwrap_or(Vector::new(0.0, 0.0)),
+        }
+    }
+
+    /// The field grid of everything, the field of the charges when nothing is induced
+    pub fn field(&self) -> &FieldGrid {
+        if self.induced {
+            &self.field
+        } else {
+            &self.free_field
+        }
+    }
+
+    /// The lowest and highest potential of the field grid
+    pub fn potential_range(&self) -> (f64, f64) {
+        self.field()
+            .potentials()
+            .filter(|potential| potential.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), potential| {
+                (min.min(potential), max.max(potential))
+            })
+    }
+
+    /// The equipotential curves at `level`, as polylines in tiles' coordinates
+    /// (in magnetostatics the potential is Az, so they are the field lines)
+    pub fn equipotentials(&self, level: f64) -> Vec<Vec<Vector>> {
+        let ratio = self.field.ratio as f64;
+        let side = self.field.side();
+
+        // Every chunk is traced with the first cells of the next ones,
+        // so the pieces of the curves meet on the sides of the chunks
+        let mut pieces = Vec::new();
+        let field = self.field();
+        for (cx, cy) in field.chunks() {
+            let (x0, y0) = (cx * side, cy * side);
+            let potentials: Vec<f64> = (0..(side + 1) * (side + 1))
+                .map(|i| {
+                    let (x, y) = (x0 + i % (side + 1), y0 + i / (side + 1));
+                    field
+                        .cell(x, y)
+                        .map_or(f64::NAN, |(_, potential)| potential)
+                })
+                .collect();
+
+            let offset = Vector::new(x0 as f64, y0 as f64);
+            pieces.extend(
+                contour(&potentials, side + 1, side + 1, level)
+                    .into_iter()
+                    .map(|polyline| polyline.iter().map(|point| point + offset).collect()),
+            );
+        }
+
+        // The samples are on the centers of the field cells
+        join_polylines(pieces)
+            .into_iter()
+            .map(|polyline| {
+                polyline
+                    .iter()
+                    .map(|point| (point + Vector::new(0.5, 0.5)) / ratio)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The equipotential curves at every multiple of `spacing`, with their level
+    pub fn even_equipotentials(&self, spacing: f64) -> Vec<(f64, Vec<Vec<Vector>>)> {
+        let (min, max) = self.potential_range();
+        even_levels(min, max, spacing)
+            .into_iter()
+            .map(|level| (level, self.equipotentials(level)))
+            .collect()
+    }
+
+    /// Can a moving particle be at `position`?
+    /// (it must be in the world and not in a charge, a conductor or a mirror)
+    fn is_free(&self, position: &Vector) -> bool {
+        let (x, y) = (position.x.floor() as i32, position.y.floor() as i32);
+        self.in_bounds(x, y)
+            && !self.is_solid(x as usize, y as usize)
+            && !self.in_mirror(position)
+            && self.charge_at(position, CHARGE_RADIUS).is_none()
+    }
+
+    /// Follow a test `particle` through the calculated field, sampled like the field lines
+    /// The particle stops when it leaves the world or hits a charge, a conductor or a mirror
+    /// In magnetostatics the particle is a wire carrying the current `q`
+    /// A particle without a positive mass isn't traced
+    pub fn trace_particle(
+        &self,
+        particle: &TestParticle,
+        settings: &TrajectorySettings,
+    ) -> Trajectory {
+        let motion = settings.motion;
+        let time_step = settings.time_step;
+        let use_tree = self.use_tree_for_lines();
+        let force = |position: &Vector| {
+            get_force(self.mode, particle.q, &self.line_force(position, use_tree))
+        };
+
+        let mut trajectory = Trajectory {
+            particle: *particle,
+            points: Vec::new(),
+            segments: Vec::new(),
+        };
+        let massive = particle.mass.is_finite() && particle.mass > 0.0;
+        if !self.is_free(&particle.position) || !massive {
+            return trajectory;
+        }
+
+        let mut position = particle.position;
+        let mut momentum = motion.momentum(&particle.velocity, particle.mass);
+        let mut current_force = force(&position);
+        let mut segment = Vec::new();
+
+        for step in 0..settings.max_steps {
+            trajectory.points.push(TrajectoryPoint {
+                time: step as f64 * time_step,
+                position,
+                velocity: motion.velocity(&momentum, particle.mass),
+            });
+            segment.push(position);
+
+            // Kick, drift and kick again (velocity Verlet written with the momentum)
+            momentum += current_force * time_step / 2.0;
+            let old_position = position;
+            position += motion.velocity(&momentum, particle.mass) * time_step;
+
+            // Past a periodic edge the path continues on the other side
+            if self.wrap_position(&mut position) {
+                segment.push(old_position);
+                trajectory.segments.push(segment);
+                segment = Vec::new();
+            }
+
+            if !self.is_free(&position) {
+                segment.push(position);
+                break;
+            }
+
+            current_force = force(&position);
+            momentum += current_force * time_step / 2.0;
+        }
+
+        trajectory.segments.push(segment);
+        trajectory
+    }
+
+    /// Bring `position` back in the world if it crossed a periodic edge
+    /// Returns true when the position has been moved
+    pub fn wrap_position(&self, position: &mut Vector) -> bool {
+        let (width, height) = (self.width as f64, self.height as f64);
+        let mut wrapped = false;
+
+        if self.boundaries.periodic_x() && (position.x < 0.0 || position.x >= width) {
+            position.x = (position.x % width + width) % width;
+            wrapped = true;
+        }
+        if self.boundaries.periodic_y() && (position.y < 0.0 || position.y >= height) {
+            position.y = (position.y % height + height) % height;
+            wrapped = true;
+        }
+
+        wrapped
+    }
+
+    /// Calculate the closed field lines of the magnetic field around the currents
+    fn calculate_loops(&self) -> Vec<Vec<Vector>> {
+        let max_length = 4000;
+        let step = 1.0 / self.field.ratio as f64;
+
+        // Start the loops at growing distances on the right of every current
+        let mut seeds = Vec::new();
+        let mut currents: Vec<Vector> = self.get_charges()
+            .iter()
+            .map(|&(x, y)| Vector::new(x as f64 + 0.5, y as f64 + 0.5))
+            .collect();
+        currents.extend(self.charges.iter().map(|charge| charge.position));
+
+        for current in &currents {
+            let mut distance = 1.5;
+            while self.in_bounds((current.x + distance) as i32, current.y as i32) {
+                seeds.push(current + Vector::new(distance, 0.0));
+                distance *= 2.0;
+            }
+        }
+
+        let use_tree = self.use_tree_for_lines();
+        let mut loops = Vec::new();
+        for &start in &seeds {
+            let mut line = vec![start];
+            let mut position = start;
+            let mut old_direction = Vector::new(0.0, 0.0);
+            let mut wraps = 0;
+
+            let mut length = 0;
+            while length < max_length {
+                let force = &self.line_force(&position, use_tree);
+                if force.norm() == 0.0 {
+                    break;
+                }
+
+                // Take the direction at the middle of the step,
+                // so the loops don't spiral away and actually close
+                let mut middle = position + force.normalize() * step / 2.0;
+                self.wrap_position(&mut middle);
+                if !self.in_bounds(middle.x.floor() as i32, middle.y.floor() as i32) {
+                    break;
+                }
+                let direction = self.line_force(&middle, use_tree).normalize();
+                position += direction * step;
+                length += 1;
+
+                // Past a periodic edge the loop continues on the other side
+                if self.wrap_position(&mut position) {
+                    loops.push(line);
+                    line = vec![position];
+
+                    wraps += 1;
+                    if wraps > MAX_WRAPS {
+                        break;
+                    }
+                }
+
+                if !self.in_bounds(position.x.floor() as i32, position.y.floor() as i32) {
+                    break;
+                }
+
+                // The loop is closed when it gets back to the start
+                if length > 4 && (position - start).norm() < 2.0 * step {
+                    line.push(start);
+                    break;
+                }
+
+                // Only push the points where the direction changes
+                if (direction - old_direction).norm() > 0.1 {
+                    line.push(position);
+                    old_direction = direction;
+                }
+            }
+
+            if line.len() > 1 {
+                loops.push(line);
+            }
+        }
+
+        loops
+    }
+}
+
+/// Add the field of `sources` to the allocated chunks of `grid`
+/// The charges sitting on the center of a field cell are added all at once
+/// with a convolution when there are many of them
+fn add_sources(
+    grid: &mut FieldGrid,
+    sources: Vec<(Vector, f64)>,
+    mode: Mode,
+    law: FieldLaw,
+    progress: &Progress,
+) {
+    let ratio = grid.ratio as f64;
+    let (width, height) = (grid.width, grid.height);
+    let (grid_sources, mut sources): (Vec<_>, Vec<_>) =
+        sources.into_iter().partition(|&(position, _)| {
+            let cell = position * ratio - Vector::new(0.5, 0.5);
+            cell.x >= 0.0 && cell.y >= 0.0 && cell.x < width as f64 && cell.y < height as f64
+                && (cell.x - cell.x.round()).abs() < 1e-9
+                && (cell.y - cell.y.round()).abs() < 1e-9
+        });
+
+    // The convolution goes through the whole grid, even where it's not allocated
+    let cells = (width * height) as f64;
+    let allocated = grid.allocated_cells().max(1) as f64;
+    let threshold = CONVOLUTION_THRESHOLD * cells.log2() * cells / allocated;
+    if grid_sources.len() as f64 > threshold {
+        let grid_sources: Vec<_> = grid_sources
+            .iter()
+            .map(|&(position, charge)| {
+                let cell = position * ratio - Vector::new(0.5, 0.5);
+                (cell.x.round() as usize, cell.y.round() as usize, charge)
+            })
+            .collect();
+        convolve_sources(grid, &grid_sources, mode, law, progress);
+    } else {
+        sources.extend(grid_sources);
+    }
+
+    if sources.is_empty() {
+        return;
+    }
+
+    // Every source goes through a whole row before the next one,
+    // the row stays in the cache while all the sources are added to it
+    grid.for_each_span(progress, |mut span| {
+        // The position of the cells of the row on the tiles grid
+        let y = (span.y as f64 + 0.5) / ratio;
+        let columns: Vec<f64> = (span.x..span.x + span.len())
+            .map(|x| (x as f64 + 0.5) / ratio)
+            .collect();
+
+        for &(ref position, charge) in &sources {
+            match (mode, law) {
+                (Mode::Electrostatic, FieldLaw::PointCharges) => {
+                    add_to_span(&mut span, &columns, y, position, |delta| {
+                        get_field(FieldLaw::PointCharges, charge, delta)
+                    })
+                }
+                (Mode::Electrostatic, FieldLaw::LineCharges) => {
+                    add_to_span(&mut span, &columns, y, position, |delta| {
+                        get_field(FieldLaw::LineCharges, charge, delta)
+                    })
+                }
+                (Mode::Magnetostatic, _) => {
+                    add_to_span(&mut span, &columns, y, position, |delta| {
+                        get_magnetic_field(charge, delta)
+                    })
+                }
+            }
+        }
+    });
+}
+
+#[inline(always)]
+/// Add `field` of the distance from `source` to every cell of `span`,
+/// the cells being at `columns` (x) and `y` on the tiles grid
+/// The law is picked before the loop, so the loop along the row has no branches
+/// and can be vectorized
+fn add_to_span<F>(span: &mut RowSpan, columns: &[f64], y: f64, source: &Vector, field: F)
+where
+    F: Fn(&Vector) -> (Vector, f64),
+{
+    let dy = y - source.y;
+    let cells = span.ex
+        .iter_mut()
+        .zip(span.ey.iter_mut())
+        .zip(span.potential.iter_mut())
+        .zip(columns);
+    for (((ex, ey), potential), x) in cells {
+        // The distance between the position of the source
+        // and the position of the field tile we are updating
+        let (cell_field, cell_potential) = field(&Vector::new(x - source.x, dy));
+        *ex += cell_field.x;
+        *ey += cell_field.y;
+        *potential += cell_potential;
+    }
+}
+
+/// Add the field of `sources` (x, y, charge), sitting on the centers of the field cells,
+/// as the convolution of the charges with the field of a unit charge
+/// The convolution is done in the frequency domain, so the time doesn't depend
+/// on the number of charges
+fn convolve_sources(
+    grid: &mut FieldGrid,
+    sources: &[(usize, usize, f64)],
+    mode: Mode,
+    law: FieldLaw,
+    progress: &Progress,
+) {
+    let ratio = grid.ratio as f64;
+    let (width, height) = (grid.width, grid.height);
+
+    // Pad the grids so that the circular convolution doesn't wrap around
+    let padded_width = (2 * width).next_power_of_two();
+    let padded_height = (2 * height).next_power_of_two();
+    let size = padded_width * padded_height;
+
+    let mut charges = vec![Complex::zero(); size];
+    for &(x, y, charge) in sources {
+        charges[y * padded_width + x].re += charge;
+    }
+
+    // The field (packed as Ex + i Ey) and the potential of a unit charge at every offset
+    let mut field = vec![Complex::zero(); size];
+    let mut potential = vec![Complex::zero(); size];
+    for dy in -(height as i32 - 1)..height as i32 {
+        for dx in -(width as i32 - 1)..width as i32 {
+            let delta = Vector::new(dx as f64, dy as f64) / ratio;
+            let (unit_field, unit_potential) = source_field(mode, law, 1.0, &delta);
+
+            // Negative offsets wrap around to the end of the padded grid
+            let x = (dx + padded_width as i32) as usize % padded_width;
+            let y = (dy + padded_height as i32) as usize % padded_height;
+            field[y * padded_width + x] = Complex::new(unit_field.x, unit_field.y);
+            potential[y * padded_width + x] = Complex::new(unit_potential, 0.0);
+        }
+    }
+
+    fft_2d(&mut charges, padded_width, padded_height, false);
+    fft_2d(&mut field, padded_width, padded_height, false);
+    fft_2d(&mut potential, padded_width, padded_height, false);
+
+    // The convolution is a product in the frequency domain
+    for i in 0..size {
+        field[i] = field[i] * charges[i];
+        potential[i] = potential[i] * charges[i];
+    }
+
+    fft_2d(&mut field, padded_width, padded_height, true);
+    fft_2d(&mut potential, padded_width, padded_height, true);
+
+    grid.for_each_cell(progress, |x, y, cell| {
+        let i = y * padded_width + x;
+        cell.0 += Vector::new(field[i].re, field[i].im);
+        cell.1 += potential[i].re;
+    });
+}
+
+/// Clear the field inside of the `mirrors`, leaving the potential of their surface
+fn ground_mirrors(
+    field: &mut FieldGrid,
+    mirrors: &[Mirror],
+    potentials: &[f64],
+    progress: &Progress,
+) {
+    if potentials.is_empty() {
+        return;
+    }
+
+    let ratio = field.ratio as f64;
+    field.for_each_cell(progress, |x, y, cell| {
+        let position = Vector::new(x as f64 + 0.5, y as f64 + 0.5) / ratio;
+        for (mirror, &potential) in mirrors.iter().zip(potentials) {
+            if mirror.contains(&position) {
+                *cell = (Vector::new(0.0, 0.0), potential);
+            }
+        }
+    });
+}
+
+#[inline]
+/// Calculate the field & potential of a single charge (or current) with distance `delta`
+fn source_field(mode: Mode, law: FieldLaw, charge: f64, delta: &Vector) -> (Vector, f64) {
+    match mode {
+        Mode::Electrostatic => get_field(law, charge, delta),
+        Mode::Magnetostatic => get_magnetic_field(charge, delta),
+    }
+}
+
+#[inline]
+/// Calculate the magnetic field & vector potential of a wire carrying `current`
+/// with distance `delta`
+fn get_magnetic_field(current: f64, delta: &Vector) -> (Vector, f64) {
+    let distance = delta.norm();
+
+    if distance != 0.0 {
+        // The field circles around the wire, counterclockwise for positive currents
+        let tangent = Vector::new(-delta.y, delta.x) / distance;
+        (tangent * current / distance, -current * distance.ln())
+    } else {
+        (Vector::new(0.0, 0.0), 0.0)
+    }
+}
+
+#[inline]
+/// Calculate the eletric field & potential of `charge` with distance `delta`
+fn get_field(law: FieldLaw, charge: f64, delta: &Vector) -> (Vector, f64) {
+    match law {
+        FieldLaw::PointCharges => {
+            // A single division, so the loops over the cells can be vectorized
+            let distance_squared = delta.x * delta.x + delta.y * delta.y;
+            let inverse = if distance_squared != 0.0 {
+                1.0 / distance_squared.sqrt()
+            } else {
+                0.0
+            };
+            let potential = charge * inverse;
+            (delta * (potential * inverse * inverse), potential)
+        }
+        FieldLaw::LineCharges => {
+            let distance = delta.norm();
+            if distance != 0.0 {
+                (delta.normalize() * charge / distance, -charge * distance.ln())
+            } else {
+                (Vector::new(0.0, 0.0), 0.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convolution_matches_direct_sum() {
+        let progress = Progress::new();
+        let sources = [(1, 2, 3.0), (7, 5, -2.0), (11, 0, 0.5), (4, 7, 1.5)];
+        let laws = [
+            (Mode::Electrostatic, FieldLaw::PointCharges),
+            (Mode::Electrostatic, FieldLaw::LineCharges),
+            (Mode::Magnetostatic, FieldLaw::LineCharges),
+        ];
+
+        // The offset of a cell from the first one, in tiles
+        let center = |x: usize, y: usize| Vector::new(x as f64, y as f64) / 4.0;
+
+        for &(mode, law) in &laws {
+            // 12 by 8 cells, in a single chunk
+            let mut grid = FieldGrid::new(3, 2, 4);
+            grid.allocate((0, 0));
+            convolve_sources(&mut grid, &sources, mode, law, &progress);
+
+            for y in 0..8 {
+                for x in 0..12 {
+                    let mut field = Vector::new(0.0, 0.0);
+                    let mut potential = 0.0;
+                    for &(source_x, source_y, charge) in &sources {
+                        let delta = center(x, y) - center(source_x, source_y);
+                        let (source_field, source_potential) =
+                            source_field(mode, law, charge, &delta);
+                        field += source_field;
+                        potential += source_potential;
+                    }
+
+                    let (convolved_field, convolved_potential) = grid.cell(x, y).unwrap();
+                    assert!((convolved_field - field).norm() < 1e-9);
+                    assert!((convolved_potential - potential).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn steps_use_the_forces_after_an_edit() {
+        let mut world = World::new_empty(40, 40, 1);
+        world.add_charge(Charge::new(Vector::new(10.0, 20.0), 10.0));
+        world.add_charge(Charge::new(Vector::new(12.0, 20.0), 10.0));
+        world.step();
+
+        // Drag a charge away, the next step starts from the forces where it was dropped
+        let position = world.charges[1].position + Vector::new(8.0, 0.0);
+        world.move_charge(1, position);
+        let start = world.forces()[0] / world.charges[0].mass;
+        let velocity = world.charges[0].velocity;
+        world.step();
+        let end = world.forces()[0] / world.charges[0].mass;
+
+        let expected = velocity + (start + end) * world.dynamics.time_step / 2.0;
+        assert!((world.charges[0].velocity - expected).norm() < 1e-12);
+    }
+
+    #[test]
+    fn huge_worlds_are_only_solved_around_the_materials() {
+        let mut world = World::new_empty(4096, 4096, 2);
+        world.set_law(FieldLaw::LineCharges);
+        assert!(world.update_conductor(Some(1.0), 10, 10));
+        assert!(!world.too_big_to_solve());
+
+        // The whole world would be solved, 4096 by 4096 tiles of 9 cells
+        world.set_boundaries(Boundaries::all(Boundary::Grounded));
+        assert!(world.too_big_to_solve());
+        assert!(world.capacitance().is_empty());
+        world.set_boundaries(Boundaries::all(Boundary::Open));
+        world.add_mirror(Mirror::plane(Vector::new(0.0, 5.0), Vector::new(0.0, 1.0)));
+        assert!(world.too_big_to_solve());
+
+        let mut small = World::new_empty(64, 64, 2);
+        small.set_law(FieldLaw::LineCharges);
+        small.update_conductor(Some(1.0), 10, 10);
+        small.set_boundaries(Boundaries::all(Boundary::Grounded));
+        assert!(!small.too_big_to_solve());
+    }
+
+    #[test]
+    fn small_charges_emit_a_line() {
+        let mut world = World::new_empty(40, 40, 2);
+        world.update_tile(2, 10, 20);
+        world.add_charge(Charge::new(Vector::new(30.5, 20.5), 1.0));
+        world.calculate_field();
+        let lines = world.calculate_lines();
+
+        let starts_near = |x: f64, y: f64| {
+            lines
+                .iter()
+                .any(|line| (line[0] - Vector::new(x, y)).norm() < 1.0)
+        };
+        assert!(starts_near(10.5, 20.5));
+        assert!(starts_near(30.5, 20.5));
+    }
+
+    #[test]
+    fn barnes_hut_error_shrinks_with_theta() {
+        let mut world = World::new_empty(48, 48, 2);
+        for y in (4..44).step_by(5) {
+            for x in (4..44).step_by(3) {
+                world.update_tile(((x * 7 + y * 13) % 11) as i8 - 5, x, y);
+            }
+        }
+
+        let mut errors = Vec::new();
+        for &theta in &[1.0, 0.7, 0.5, 0.0] {
+            world.set_evaluator(Evaluator::BarnesHut { theta });
+            world.calculate_field();
+            let report = world.tree_error(2000).unwrap();
+            assert!(report.samples > 1000);
+            errors.push(report.mean_error);
+        }
+
+        assert!(errors[3] < 1e-9);
+        for pair in errors.windows(2) {
+            assert!(pair[1] < pair[0]);
+        }
+    }
+
+    #[test]
+    fn capacitance_matrix_is_symmetric() {
+        let mut world = World::new_empty(48, 48, 2);
+        world.set_law(FieldLaw::LineCharges);
+        for y in 10..20 {
+            world.update_conductor(Some(1.0), 10, y);
+            // Touching the first one at another potential
+            world.update_conductor(Some(2.0), 11, y);
+        }
+        for x in 20..30 {
+            for y in 25..28 {
+                world.update_conductor(Some(3.0), x, y);
+            }
+        }
+        world.update_permittivity(4.0, 15, 15);
+        world.calculate_field();
+
+        let matrix = world.capacitance();
+        assert_eq!(matrix.len(), 3);
+        let largest = matrix[0][0].max(matrix[1][1]).max(matrix[2][2]);
+        for i in 0..3 {
+            assert!(matrix[i][i] > 0.0);
+            for j in 0..i {
+                assert!(matrix[i][j] < 0.0);
+                assert!((matrix[i][j] - matrix[j][i]).abs() < 0.01 * largest);
+            }
+        }
+    }
+
+    #[test]
+    fn capacitance_of_a_coaxial_pair() {
+        let (inner, outer) = (5.0, 15.0);
+        for &permittivity in &[1.0, 3.0] {
+            let mut world = World::new_empty(64, 64, 2);
+            world.set_law(FieldLaw::LineCharges);
+            let center = Vector::new(32.0, 32.0);
+            for y in 0..64 {
+                for x in 0..64 {
+                    let distance = (Vector::new(x as f64 + 0.5, y as f64 + 0.5) - center).norm();
+                    if distance < inner {
+                        world.update_conductor(Some(1.0), x, y);
+                    } else if distance < outer {
+                        world.update_permittivity(permittivity, x, y);
+                    } else if distance < outer + 3.0 {
+                        world.update_conductor(Some(0.0), x, y);
+                    }
+                }
+            }
+            world.calculate_field();
+
+            // The potential of a line charge is -q ln r, without the 2π of Gauss's law
+            let expected = permittivity / (outer / inner).ln();
+            let matrix = world.capacitance();
+            let bodies = world.conductor_bodies();
+            let core = bodies
+                .iter()
+                .position(|body| body.potential == 1.0)
+                .unwrap();
+            assert!((matrix[core][core] - expected).abs() < 0.02 * expected);
+            assert!((matrix[1 - core][core] + expected).abs() < 0.02 * expected);
+        }
+    }
+}
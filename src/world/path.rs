@@ -0,0 +1,86 @@
+use Vector;
+
+/// The longest piece of a path along which the field is taken as constant, in tiles
+const PATH_STEP: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The field integrated along a path, and the potential difference between its ends
+pub struct LineIntegral {
+    // The integral of E · dl, the work done by the field on a unit charge
+    pub integral: f64,
+    // V(start) - V(end), equal to the integral for an electrostatic field,
+    // `None` when an end is out of the world
+    pub voltage: Option<f64>,
+    // The length of the path
+    pub length: f64,
+}
+
+/// The integral of `field` · dl along the polyline through `points`, and its length
+/// `field` is taken in the middle of short pieces of the segments,
+/// and counts as zero where it's `None`
+pub fn integrate<F>(points: &[Vector], field: F) -> (f64, f64)
+where
+    F: Fn(&Vector) -> Option<Vector>,
+{
+    let mut integral = 0.0;
+    let mut length = 0.0;
+
+    for pair in points.windows(2) {
+        let segment = pair[1] - pair[0];
+        let pieces = (segment.norm() / PATH_STEP).ceil().max(1.0) as usize;
+        let piece = segment / pieces as f64;
+
+        for i in 0..pieces {
+            let middle = pair[0] + piece * (i as f64 + 0.5);
+            if let Some(field) = field(&middle) {
+                integral += field.dot(&piece);
+            }
+        }
+        length += segment.norm();
+    }
+
+    (integral, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The field and the potential (-q ln r) of line charges at `position`
+    fn line_charges(charges: &[(Vector, f64)], position: &Vector) -> (Vector, f64) {
+        charges.iter().fold(
+            (Vector::new(0.0, 0.0), 0.0),
+            |(field, potential), &(charge, q)| {
+                let delta = position - charge;
+                let distance = delta.norm();
+                (
+                    field + delta * q / (distance * distance),
+                    potential - q * distance.ln(),
+                )
+            },
+        )
+    }
+
+    #[test]
+    fn integral_is_the_potential_difference() {
+        let charges = [(Vector::new(0.0, 0.0), 2.0), (Vector::new(4.0, 1.0), -1.0)];
+        let field = |position: &Vector| Some(line_charges(&charges, position).0);
+        let (start, end) = (Vector::new(-2.0, -1.5), Vector::new(6.0, 3.0));
+        let voltage = line_charges(&charges, &start).1 - line_charges(&charges, &end).1;
+
+        let paths = [
+            vec![start, end],
+            vec![start, Vector::new(-2.0, 4.0), Vector::new(3.0, 5.0), end],
+            vec![start, Vector::new(2.0, -3.0), Vector::new(7.0, -1.0), end],
+        ];
+        for path in &paths {
+            let (integral, _) = integrate(path, field);
+            assert!((integral - voltage).abs() < 1e-3 * voltage.abs());
+        }
+
+        // Around a closed path the field does no work
+        let (integral, length) = integrate(&[start, end, Vector::new(-3.0, 5.0), start], field);
+        assert!(integral.abs() < 1e-3);
+        assert!(length > 0.0);
+    }
+}
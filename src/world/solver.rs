@@ -0,0 +1,778 @@
+use parallel::Progress;
+use world::boundaries::{Boundaries, Boundary};
+use world::chunks::Chunks;
+use Vector;
+
+use std::f64;
+
+/// The smoothing sweeps done before and after each coarse grid correction
+const SMOOTHING_SWEEPS: usize = 2;
+/// The sweeps done on the coarsest grid, where the error is solved almost exactly
+const COARSEST_SWEEPS: usize = 50;
+/// Grids with a side shorter than this are not coarsened further
+const COARSEST_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The method used to solve the finite-difference equations
+pub enum Method {
+    /// Successive over-relaxation, simple but slow on big grids
+    Relaxation,
+    /// Multigrid V-cycles, going down from the field grid to the tiles grid and below
+    Multigrid,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// The parameters of the solver
+pub struct SolverSettings {
+    pub method: Method,
+    // The maximum number of sweeps done for each solve by the relaxation
+    pub max_iterations: usize,
+    // The maximum number of V-cycles done for each solve by the multigrid
+    pub max_cycles: usize,
+    // The solver stops when the residual is less than this
+    pub tolerance: f64,
+    // The over-relaxation factor, must be between 1 and 2
+    pub omega: f64,
+}
+
+impl SolverSettings {
+    pub fn new() -> SolverSettings {
+        SolverSettings {
+            method: Method::Multigrid,
+            max_iterations: 5000,
+            max_cycles: 50,
+            tolerance: 1e-3,
+            omega: 1.9,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A finite-difference solver for the potential induced by conductors and dielectrics
+///
+/// The field of the charges is already known from the superposition,
+/// so the solver only looks for the correction that must be added to it
+/// to keep every conductor at its fixed potential.
+/// The total potential satisfies the generalized Poisson equation div(εr grad V) = -ρ,
+/// so the correction is sourced by the bound charge at the dielectric interfaces.
+/// Past grounded edges the correction vanishes,
+/// past insulating edges it is mirrored and past periodic edges it repeats.
+/// Past open edges it is the potential of its own charge seen from far away,
+/// -Q ln(r) from the center of the materials (see `solve`).
+/// When every edge is open the grid only needs to cover the materials and their surroundings,
+/// the correction of the rest of the world is the one past its edges.
+pub struct Solver {
+    pub settings: SolverSettings,
+    // The conditions on the edges of the field grid
+    pub boundaries: Boundaries,
+    // The correction to the potential on the solved grid, row by row
+    // It is kept between solves so the next one starts from a close solution,
+    // and only allocated by the first one
+    potential: Vec<f64>,
+    // The two corrections superposed in `potential`: the one holding the conductors
+    // with nothing past the edges, and the one of a unit charge past the open edges
+    held: Vec<f64>,
+    unit: Vec<f64>,
+    // The charge of the correction seen from past the open edges,
+    // and where it is seen from (the center of the materials, in cells of the solved grid)
+    far_charge: f64,
+    center: Vector,
+    // The first field cell of the solved grid and its size, in field cells
+    origin: (usize, usize),
+    width: usize,
+    height: usize,
+    // The number of field cells on the side of a tile
+    ratio: usize,
+    // The number of sweeps (or V-cycles) used by the last solve
+    iterations: usize,
+    // The residual left by the last solve
+    residual: f64,
+}
+
+impl Solver {
+    /// A solver for a field grid with `ratio` cells on a tile's side
+    pub fn new(ratio: usize) -> Solver {
+        Solver {
+            settings: SolverSettings::new(),
+            boundaries: Boundaries::all(Boundary::Open),
+            potential: Vec::new(),
+            held: Vec::new(),
+            unit: Vec::new(),
+            far_charge: 0.0,
+            center: Vector::new(0.0, 0.0),
+            origin: (0, 0),
+            width: 0,
+            height: 0,
+            ratio,
+            iterations: 0,
+            residual: 0.0,
+        }
+    }
+
+    /// The number of sweeps (or V-cycles) used by the last solve
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// The residual left by the last solve:
+    /// the largest change a Jacobi sweep would still make to the correction
+    pub fn residual(&self) -> f64 {
+        self.residual
+    }
+
+    #[inline]
+    /// The correction to the potential on a cell of the solved grid
+    fn potential(&self, x: usize, y: usize) -> f64 {
+        self.potential.get(y * self.width + x).cloned().unwrap_or(0.0)
+    }
+
+    /// The correction to the potential on any field cell, even outside of the solved grid
+    pub fn correction(&self, x: i32, y: i32) -> f64 {
+        let (x, y) = (x - self.origin.0 as i32, y - self.origin.1 as i32);
+        let boundaries = &self.boundaries;
+        let wrapped_x = wrap(x, self.width, boundaries.left, boundaries.right);
+        let wrapped_y = wrap(y, self.height, boundaries.bottom, boundaries.top);
+        if let (Some(x), Some(y)) = (wrapped_x, wrapped_y) {
+            return self.potential(x, y);
+        }
+
+        let grounded = |coordinate: i32, length: usize, low: Boundary, high: Boundary| {
+            coordinate < 0 && low == Boundary::Grounded
+                || coordinate >= length as i32 && high == Boundary::Grounded
+        };
+        if grounded(x, self.width, boundaries.left, boundaries.right)
+            || grounded(y, self.height, boundaries.bottom, boundaries.top)
+        {
+            0.0
+        } else {
+            self.far_charge * self.unit_potential(x as f64 + 0.5, y as f64 + 0.5)
+        }
+    }
+
+    /// The correction to the field & potential on any field cell, even outside of the solved grid
+    /// The field is minus the gradient of the correction
+    pub fn correction_field(&self, x: i32, y: i32) -> (Vector, f64) {
+        let gradient = Vector::new(
+            self.correction(x + 1, y) - self.correction(x - 1, y),
+            self.correction(x, y + 1) - self.correction(x, y - 1),
+        ) * self.ratio as f64 / 2.0;
+
+        (-gradient, self.correction(x, y))
+    }
+
+    /// The potential of a unit line charge on the center of the materials, -ln(r),
+    /// at (`x`, `y`) in cells of the solved grid
+    fn unit_potential(&self, x: f64, y: f64) -> f64 {
+        let distance = (Vector::new(x, y) - self.center).norm() / self.ratio as f64;
+
+        if distance > 0.0 {
+            -distance.ln()
+        } else {
+            0.0
+        }
+    }
+
+    /// Take the solution found by `solved`, a copy of this solver,
+    /// keeping the settings and the edges which may have changed since it was copied
+    pub fn take_solution(&mut self, solved: Solver) {
+        self.potential = solved.potential;
+        self.held = solved.held;
+        self.unit = solved.unit;
+        self.far_charge = solved.far_charge;
+        self.center = solved.center;
+        self.origin = solved.origin;
+        self.width = solved.width;
+        self.height = solved.height;
+        self.iterations = solved.iterations;
+        self.residual = solved.residual;
+    }
+
+    /// Forget the previous solution
+    pub fn reset(&mut self) {
+        self.potential = Vec::new();
+        self.held = Vec::new();
+        self.unit = Vec::new();
+        self.far_charge = 0.0;
+        self.residual = 0.0;
+    }
+
+    /// Solve the correction to the field of the charges, whose potential on a field cell
+    /// is given by `free` (only read on the conductors and the dielectric interfaces)
+    /// `conductors` holds the fixed potential of each tile (if it is a conductor)
+    /// and `permittivity` the relative permittivity of each tile
+    /// The grid covers the tiles from the first to the last of `tiles` (min x, min y,
+    /// max x, max y), which must be the whole world unless every edge is open
+    ///
+    /// The correction holding the conductors with nothing past the edges has a charge Q1.
+    /// The one of a unit charge on the center, held past the open edges with grounded
+    /// conductors, has a charge Q2. Their sum with the weight Q = Q1 / (1 - Q2) has
+    /// the charge Q, and is the potential of this charge past the open edges.
+    pub fn solve<F>(
+        &mut self,
+        conductors: &Chunks<Option<f64>>,
+        permittivity: &Chunks<f64>,
+        free: F,
+        tiles: (usize, usize, usize, usize),
+        progress: &Progress,
+    ) where
+        F: Fn(usize, usize) -> f64,
+    {
+        let ratio = self.ratio;
+        let (min_x, min_y, max_x, max_y) = tiles;
+        let origin = (min_x * ratio, min_y * ratio);
+        let (width, height) = ((max_x - min_x + 1) * ratio, (max_y - min_y + 1) * ratio);
+        // The previous solution is only a good start on the same grid
+        if (origin, width, height) != (self.origin, self.width, self.height) {
+            self.reset();
+            self.origin = origin;
+            self.width = width;
+            self.height = height;
+        }
+        for buffer in &mut [&mut self.potential, &mut self.held, &mut self.unit] {
+            if buffer.len() != width * height {
+                **buffer = vec![0.0; width * height];
+            }
+        }
+        // The position of the cells in the field grid
+        let free = |x: usize, y: usize| free(origin.0 + x, origin.1 + y);
+        let tile = |x: usize, y: usize| ((origin.0 + x) / ratio, (origin.1 + y) / ratio);
+
+        // The value the correction must have on each conductor cell
+        let mut fixed = vec![false; width * height];
+        let mut epsilon = vec![1.0; width * height];
+        let (mut sum, mut count) = (Vector::new(0.0, 0.0), 0.0);
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                let (tile_x, tile_y) = tile(x, y);
+                if let Some(potential) = conductors.get(tile_x, tile_y) {
+                    fixed[i] = true;
+                    self.held[i] = potential - free(x, y);
+                    self.unit[i] = 0.0;
+                }
+                epsilon[i] = permittivity.get(tile_x, tile_y);
+
+                if fixed[i] || epsilon[i] != 1.0 {
+                    sum += Vector::new(x as f64 + 0.5, y as f64 + 0.5);
+                    count += 1.0;
+                }
+            }
+        }
+        self.center = if count > 0.0 {
+            sum / count
+        } else {
+            Vector::new(width as f64, height as f64) / 2.0
+        };
+
+        let grid = Level::new(width, height, 1, 1, epsilon, fixed, self.boundaries);
+
+        // The bound charge each cell holds
+        let mut source = vec![0.0; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                for &(neighbor, weight) in &grid.links[i] {
+                    match neighbor {
+                        // The polarization flux through the face, only between two permittivities
+                        Some(n) if weight != 1.0 => {
+                            let difference = free(n % width, n / width) - free(x, y);
+                            source[i] += (weight - 1.0) * difference;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        // The faces on the edges (cell, coupling, potential of the unit charge past them)
+        let mut edges = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                let sides = [
+                    (-1, 0, self.boundaries.left),
+                    (1, 0, self.boundaries.right),
+                    (0, -1, self.boundaries.bottom),
+                    (0, 1, self.boundaries.top),
+                ];
+                for (&(neighbor, weight), &(dx, dy, boundary)) in grid.links[i].iter().zip(&sides) {
+                    if neighbor.is_none() {
+                        let (x, y) = ((x as i32 + dx) as f64 + 0.5, (y as i32 + dy) as f64 + 0.5);
+                        let outside = if boundary == Boundary::Grounded {
+                            0.0
+                        } else {
+                            self.unit_potential(x, y)
+                        };
+                        edges.push((i, weight, outside));
+                    }
+                }
+            }
+        }
+        // The charge of a correction, from the flux of its field through the edges
+        // (Gauss's law for line charges)
+        // `scale` is the charge held past the open edges
+        let charge = |potential: &[f64], scale: f64| {
+            edges.iter().fold(0.0, |charge, &(i, weight, unit)| {
+                let flux = weight * (potential[i] - scale * unit);
+                charge + flux / (2.0 * f64::consts::PI)
+            })
+        };
+
+        let levels = match self.settings.method {
+            Method::Relaxation => vec![grid],
+            Method::Multigrid => hierarchy(grid, ratio),
+        };
+
+        let (mut iterations, mut residual) =
+            iterate(&self.settings, &levels, &mut self.held, &source, progress);
+        let held_charge = charge(&self.held, 0.0);
+
+        // Only the open edges see the charge of the correction
+        self.far_charge = 0.0;
+        if edges.iter().any(|&(_, _, unit)| unit != 0.0) {
+            let mut unit_source = vec![0.0; width * height];
+            for &(i, weight, unit) in &edges {
+                unit_source[i] += weight * unit;
+            }
+            let (unit_iterations, unit_residual) =
+                iterate(&self.settings, &levels, &mut self.unit, &unit_source, progress);
+            iterations += unit_iterations;
+            residual = residual.max(unit_residual);
+
+            let unit_charge = charge(&self.unit, 1.0);
+            // A conductor whose charge can't be seen from far away needs an infinite one
+            if (1.0 - unit_charge).abs() > f64::EPSILON {
+                self.far_charge = held_charge / (1.0 - unit_charge);
+            }
+        }
+
+        for (potential, (held, unit)) in self
+            .potential
+            .iter_mut()
+            .zip(self.held.iter().zip(&self.unit))
+        {
+            *potential = held + self.far_charge * unit;
+        }
+        self.iterations = iterations;
+        self.residual = residual;
+        progress.finish();
+    }
+}
+
+/// The grids of the multigrid, from the field grid `grid` to the coarsest one
+fn hierarchy(grid: Level, ratio: usize) -> Vec<Level> {
+    // The first coarse grid is the tiles grid, where the materials are exact
+    let mut levels = vec![grid];
+    let mut factor = if ratio > 1 { ratio } else { 2 };
+    loop {
+        let coarse = {
+            let coarsest = &levels[levels.len() - 1];
+            if coarsest.width.min(coarsest.height) / factor < COARSEST_SIZE {
+                break;
+            }
+            coarsest.coarsen(factor)
+        };
+        levels.push(coarse);
+        factor = 2;
+    }
+    levels
+}
+
+/// Solve `potential` on the first of `levels` with the method of `settings`,
+/// with successive over-relaxation or multigrid V-cycles
+/// Returns the number of sweeps (or V-cycles) and the residual left
+fn iterate(
+    settings: &SolverSettings,
+    levels: &[Level],
+    potential: &mut [f64],
+    source: &[f64],
+    progress: &Progress,
+) -> (usize, f64) {
+    let grid = &levels[0];
+    let mut iterations = 0;
+    let mut residual;
+
+    match settings.method {
+        Method::Relaxation => {
+            let omega = settings.omega;
+            progress.start(settings.max_iterations);
+            loop {
+                // The over-relaxation changes each cell by omega times its residual
+                residual = grid.smooth(potential, source, omega) / omega;
+
+                iterations += 1;
+                progress.advance(1);
+                if iterations >= settings.max_iterations || residual < settings.tolerance {
+                    break;
+                }
+            }
+        }
+        Method::Multigrid => {
+            progress.start(settings.max_cycles);
+            residual = grid.norm(&grid.residuals(potential, source));
+            while iterations < settings.max_cycles && residual >= settings.tolerance {
+                v_cycle(levels, potential, source);
+                residual = grid.norm(&grid.residuals(potential, source));
+
+                iterations += 1;
+                progress.advance(1);
+            }
+        }
+    }
+
+    (iterations, residual)
+}
+
+#[derive(Debug, Clone)]
+/// One grid of the multigrid hierarchy, with its discretized equations
+struct Level {
+    width: usize,
+    height: usize,
+    // The side of the blocks of the finer grid merged in each cell
+    factor: usize,
+    // The side of the cells, in cells of the field grid
+    spacing: usize,
+    // The relative permittivity of each cell
+    epsilon: Vec<f64>,
+    // The cells where the value is imposed by a conductor
+    fixed: Vec<bool>,
+    boundaries: Boundaries,
+    // The neighbors of each cell (left, right, down, up) with their coupling
+    links: Vec<[(Option<usize>, f64); 4]>,
+}
+
+impl Level {
+    fn new(
+        width: usize,
+        height: usize,
+        factor: usize,
+        spacing: usize,
+        epsilon: Vec<f64>,
+        fixed: Vec<bool>,
+        boundaries: Boundaries,
+    ) -> Level {
+        let mut links = vec![[(None, 0.0); 4]; width * height];
+        let spacing = spacing as f64;
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                let neighbors = [
+                    (x as i32 - 1, y as i32),
+                    (x as i32 + 1, y as i32),
+                    (x as i32, y as i32 - 1),
+                    (x as i32, y as i32 + 1),
+                ];
+
+                for (link, &(nx, ny)) in links[i].iter_mut().zip(neighbors.iter()) {
+                    let nx = wrap(nx, width, boundaries.left, boundaries.right);
+                    let ny = wrap(ny, height, boundaries.bottom, boundaries.top);
+                    *link = match (nx, ny) {
+                        (Some(nx), Some(ny)) => {
+                            let n = ny * width + nx;
+                            // A conductor has no permittivity of its own,
+                            // its faces are in the dielectric touching it
+                            let weight = match (fixed[i], fixed[n]) {
+                                (true, false) => epsilon[n],
+                                (false, true) => epsilon[i],
+                                _ => harmonic_mean(epsilon[i], epsilon[n]),
+                            };
+                            (Some(n), weight)
+                        }
+                        // The value is zero half a field cell past the edge,
+                        // whatever the size of the cells of this grid
+                        _ => (None, epsilon[i] * 2.0 * spacing / (spacing + 1.0)),
+                    };
+                }
+            }
+        }
+
+        Level {
+            width,
+            height,
+            factor,
+            spacing: spacing as usize,
+            epsilon,
+            fixed,
+            boundaries,
+            links,
+        }
+    }
+
+    /// The grid merging blocks of `factor` × `factor` cells
+    /// In 2D the flux through a face doesn't depend on the cell size,
+    /// so the coarse equations have the same form with the mean permittivity
+    fn coarsen(&self, factor: usize) -> Level {
+        let width = (self.width + factor - 1) / factor;
+        let height = (self.height + factor - 1) / factor;
+
+        let mut epsilon = vec![0.0; width * height];
+        let mut count = vec![0.0; width * height];
+        let mut fixed = vec![false; width * height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                let c = (y / factor) * width + x / factor;
+                epsilon[c] += self.epsilon[i];
+                count[c] += 1.0;
+                fixed[c] |= self.fixed[i];
+            }
+        }
+        for (epsilon, count) in epsilon.iter_mut().zip(count) {
+            *epsilon /= count;
+        }
+
+        let spacing = self.spacing * factor;
+        Level::new(
+            width,
+            height,
+            factor,
+            spacing,
+            epsilon,
+            fixed,
+            self.boundaries,
+        )
+    }
+
+    /// Red-black Gauss-Seidel sweep, over-relaxed by `omega`
+    /// Returns the largest change made to a cell
+    fn smooth(&self, potential: &mut [f64], source: &[f64], omega: f64) -> f64 {
+        let mut max_change: f64 = 0.0;
+
+        // Each half sweep only reads the cells of the other color
+        for color in 0..2 {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let i = y * self.width + x;
+                    if (x + y) % 2 != color || self.fixed[i] {
+                        continue;
+                    }
+
+                    let mut sum = source[i];
+                    let mut total = 0.0;
+                    for &(neighbor, weight) in &self.links[i] {
+                        if let Some(n) = neighbor {
+                            sum += weight * potential[n];
+                        }
+                        total += weight;
+                    }
+
+                    let change = omega * (sum / total - potential[i]);
+                    potential[i] += change;
+                    max_change = max_change.max(change.abs());
+                }
+            }
+        }
+
+        max_change
+    }
+
+    /// The residual of the equation of each cell (zero on the fixed cells)
+    fn residuals(&self, potential: &[f64], source: &[f64]) -> Vec<f64> {
+        let mut residuals = vec![0.0; potential.len()];
+        for (i, residual) in residuals.iter_mut().enumerate() {
+            if self.fixed[i] {
+                continue;
+            }
+
+            *residual = source[i];
+            for &(neighbor, weight) in &self.links[i] {
+                if let Some(n) = neighbor {
+                    *residual += weight * potential[n];
+                }
+                *residual -= weight * potential[i];
+            }
+        }
+        residuals
+    }
+
+    /// The largest residual, scaled to the change a Jacobi sweep would make
+    fn norm(&self, residuals: &[f64]) -> f64 {
+        residuals
+            .iter()
+            .zip(&self.links)
+            .map(|(residual, links)| {
+                let total: f64 = links.iter().map(|&(_, weight)| weight).sum();
+                (residual / total).abs()
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Improve `potential` on the first of `levels` with one V-cycle
+fn v_cycle(levels: &[Level], potential: &mut [f64], source: &[f64]) {
+    let grid = &levels[0];
+    if levels.len() == 1 {
+        for _ in 0..COARSEST_SWEEPS {
+            grid.smooth(potential, source, 1.0);
+        }
+        return;
+    }
+
+    // Wider blocks leave longer waves for the smoother to remove
+    let sweeps = SMOOTHING_SWEEPS.max(levels[1].factor);
+    for _ in 0..sweeps {
+        grid.smooth(potential, source, 1.0);
+    }
+
+    // The residual is spread on the coarse grid with the weights of the interpolation,
+    // it is the source of the error there
+    let coarse = &levels[1];
+    let residuals = grid.residuals(potential, source);
+    let mut coarse_source = vec![0.0; coarse.width * coarse.height];
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            for &(c, weight) in &interpolation(x, y, coarse) {
+                coarse_source[c] += weight * residuals[y * grid.width + x];
+            }
+        }
+    }
+
+    let mut error = vec![0.0; coarse.width * coarse.height];
+    v_cycle(&levels[1..], &mut error, &coarse_source);
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let i = y * grid.width + x;
+            if !grid.fixed[i] {
+                for &(c, weight) in &interpolation(x, y, coarse) {
+                    potential[i] += weight * error[c];
+                }
+            }
+        }
+    }
+
+    for _ in 0..sweeps {
+        grid.smooth(potential, source, 1.0);
+    }
+}
+
+/// The cells of the `coarse` grid around the center of the fine cell (`x`, `y`)
+/// with their bilinear interpolation weights
+fn interpolation(x: usize, y: usize, coarse: &Level) -> [(usize, f64); 4] {
+    let factor = coarse.factor as f64;
+    // The position of the center of the fine cell, in coarse cells
+    let axis = |coordinate: usize, length: usize| {
+        let position = (coordinate as f64 + 0.5) / factor - 0.5;
+        let position = position.max(0.0).min((length - 1) as f64);
+        let low = position.floor() as usize;
+        (low, (low + 1).min(length - 1), position - low as f64)
+    };
+    let (x0, x1, tx) = axis(x, coarse.width);
+    let (y0, y1, ty) = axis(y, coarse.height);
+
+    let index = |x: usize, y: usize| y * coarse.width + x;
+    [
+        (index(x0, y0), (1.0 - tx) * (1.0 - ty)),
+        (index(x1, y0), tx * (1.0 - ty)),
+        (index(x0, y1), (1.0 - tx) * ty),
+        (index(x1, y1), tx * ty),
+    ]
+}
+
+/// The coordinate holding the value of `coordinate` on an axis `length` long
+fn wrap(coordinate: i32, length: usize, low: Boundary, high: Boundary) -> Option<usize> {
+    let length = length as i32;
+    let periodic = low == Boundary::Periodic && high == Boundary::Periodic;
+
+    if coordinate >= 0 && coordinate < length {
+        Some(coordinate as usize)
+    } else if periodic {
+        Some(((coordinate % length + length) % length) as usize)
+    } else {
+        let boundary = if coordinate < 0 { low } else { high };
+        match boundary {
+            // The correction is mirrored, so no field crosses the edge
+            Boundary::Insulating => Some(if coordinate < 0 { 0 } else { length as usize - 1 }),
+            _ => None,
+        }
+    }
+}
+
+#[inline]
+/// The permittivity of the face between two cells
+fn harmonic_mean(a: f64, b: f64) -> f64 {
+    2.0 * a * b / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use world::boundaries::Edge;
+
+    /// A field grid of 64 × 48 cells (with 2 cells per tile) holding a dielectric block
+    /// and a conductor at a potential of 1, with a source spread over it
+    fn test_problem(boundaries: Boundaries) -> (Vec<Level>, Vec<f64>, Vec<f64>) {
+        let (width, height) = (64, 48);
+        let mut epsilon = vec![1.0; width * height];
+        let mut fixed = vec![false; width * height];
+        let mut potential = vec![0.0; width * height];
+        let mut source = vec![0.0; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                if x >= 10 && x < 26 && y >= 8 && y < 30 {
+                    epsilon[i] = 4.0;
+                }
+                if x >= 40 && x < 46 && y >= 20 && y < 24 {
+                    fixed[i] = true;
+                    potential[i] = 1.0;
+                }
+                source[i] = ((x as f64 * 0.3).sin() + (y as f64 * 0.2).cos()) * 0.01;
+            }
+        }
+
+        let grid = Level::new(width, height, 1, 1, epsilon, fixed, boundaries);
+        (hierarchy(grid, 2), potential, source)
+    }
+
+    fn test_boundaries() -> Vec<Boundaries> {
+        vec![
+            Boundaries::all(Boundary::Grounded),
+            Boundaries::all(Boundary::Insulating),
+            Boundaries::all(Boundary::Grounded)
+                .with(Edge::Left, Boundary::Periodic)
+                .with(Edge::Right, Boundary::Periodic),
+        ]
+    }
+
+    #[test]
+    fn v_cycles_reduce_the_residual() {
+        for boundaries in test_boundaries() {
+            let (levels, mut potential, source) = test_problem(boundaries);
+            let grid = &levels[0];
+            let start = grid.norm(&grid.residuals(&potential, &source));
+
+            for _ in 0..10 {
+                v_cycle(&levels, &mut potential, &source);
+            }
+            let residual = grid.norm(&grid.residuals(&potential, &source));
+            assert!(residual < start * 1e-4, "{:?}", boundaries);
+        }
+    }
+
+    #[test]
+    fn multigrid_matches_relaxation() {
+        let progress = Progress::new();
+        let mut settings = SolverSettings::new();
+        settings.tolerance = 1e-10;
+        settings.max_cycles = 100;
+        settings.max_iterations = 20000;
+
+        for boundaries in test_boundaries() {
+            let (levels, start, source) = test_problem(boundaries);
+
+            let mut multigrid = start.clone();
+            settings.method = Method::Multigrid;
+            let (cycles, _) = iterate(&settings, &levels, &mut multigrid, &source, &progress);
+
+            let mut relaxation = start;
+            settings.method = Method::Relaxation;
+            let (sweeps, _) = iterate(&settings, &levels, &mut relaxation, &source, &progress);
+
+            assert!(cycles < sweeps / 10);
+            for (a, b) in multigrid.iter().zip(&relaxation) {
+                assert!((a - b).abs() < 1e-6, "{:?}: {} and {}", boundaries, a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use super::units::Units;
+use super::FieldLaw;
+use Vector;
+
+use std::io;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How a test particle moves under a force
+pub enum Motion {
+    /// Newton's law, the speed has no limit
+    Classical,
+    /// The force changes the relativistic momentum,
+    /// so the speed never reaches `speed_of_light` (in tiles per unit of time)
+    Relativistic { speed_of_light: f64 },
+}
+
+impl Motion {
+    /// The velocity of a particle of `mass` with `momentum`
+    pub fn velocity(&self, momentum: &Vector, mass: f64) -> Vector {
+        match *self {
+            Motion::Classical => momentum / mass,
+            Motion::Relativistic { speed_of_light } => {
+                let ratio = momentum.norm() / (mass * speed_of_light);
+                momentum / (mass * (1.0 + ratio * ratio).sqrt())
+            }
+        }
+    }
+
+    /// The momentum of a particle of `mass` moving at `velocity`
+    /// (the speed is kept below the speed of light)
+    pub fn momentum(&self, velocity: &Vector, mass: f64) -> Vector {
+        match *self {
+            Motion::Classical => velocity * mass,
+            Motion::Relativistic { speed_of_light } => {
+                let beta = (velocity.norm() / speed_of_light).min(0.999);
+                let gamma = 1.0 / (1.0 - beta * beta).sqrt();
+                if velocity.norm() == 0.0 {
+                    *velocity
+                } else {
+                    velocity.normalize() * beta * speed_of_light * gamma * mass
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A particle moving through the field without changing it
+pub struct TestParticle {
+    pub position: Vector,
+    pub velocity: Vector,
+    pub q: f64,
+    pub mass: f64,
+}
+
+impl TestParticle {
+    pub fn new(position: Vector, velocity: Vector, q: f64, mass: f64) -> TestParticle {
+        TestParticle {
+            position,
+            velocity,
+            q,
+            mass,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// The parameters of the tracing of test particles
+pub struct TrajectorySettings {
+    pub motion: Motion,
+    pub time_step: f64,
+    // The tracing stops after this many steps
+    pub max_steps: usize,
+}
+
+impl TrajectorySettings {
+    pub fn new() -> TrajectorySettings {
+        TrajectorySettings {
+            motion: Motion::Classical,
+            time_step: 0.01,
+            max_steps: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The state of a test particle at some time
+pub struct TrajectoryPoint {
+    pub time: f64,
+    pub position: Vector,
+    pub velocity: Vector,
+}
+
+#[derive(Debug, Clone)]
+/// The path of a test particle through the field
+pub struct Trajectory {
+    // The particle at launch
+    pub particle: TestParticle,
+    // Every step of the particle
+    pub points: Vec<TrajectoryPoint>,
+    // The pieces of the path split by the periodic edges, to be drawn like the field lines
+    pub segments: Vec<Vec<Vector>>,
+}
+
+impl Trajectory {
+    /// Write the trajectory as CSV, one line for every step (time, position, velocity)
+    /// The lengths are in meters, the times in seconds for charges of `law`,
+    /// or in the units of time of the world without `law` (in magnetostatics)
+    pub fn write_csv<W: Write>(
+        &self,
+        mut writer: W,
+        units: &Units,
+        law: Option<FieldLaw>,
+    ) -> io::Result<()> {
+        let seconds = match law {
+            Some(law) => {
+                writeln!(writer, "t (s),x (m),y (m),vx (m/s),vy (m/s)")?;
+                units.time(law, 1.0)
+            }
+            None => {
+                writeln!(writer, "t,x (m),y (m),vx (m/t),vy (m/t)")?;
+                1.0
+            }
+        };
+        for point in &self.points {
+            let position = point.position * units.cell_size;
+            let velocity = point.velocity * units.cell_size / seconds;
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                point.time * seconds,
+                position.x,
+                position.y,
+                velocity.x,
+                velocity.y
+            )?;
+        }
+
+        Ok(())
+    }
+}
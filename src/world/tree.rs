@@ -0,0 +1,187 @@
+use super::{source_field, FieldLaw, Mode};
+use Vector;
+
+/// The maximum number of charges in a leaf of the tree
+const LEAF_SIZE: usize = 8;
+/// Nodes smaller than this are never split, so coincident charges don't split forever
+const MIN_NODE_SIZE: f64 = 1e-6;
+
+#[derive(Debug, Clone)]
+/// A node of the tree, covering a square of the world
+struct Node {
+    // Half the side of the square
+    half_size: f64,
+    // The total charge in the node
+    charge: f64,
+    // The point the expansion is centered on (the center of the absolute charge)
+    origin: Vector,
+    // The dipole moment around the origin
+    dipole: Vector,
+    // The index of the four children, if the node is split
+    children: Option<[usize; 4]>,
+    // The charges held by a leaf
+    sources: Vec<(Vector, f64)>,
+}
+
+#[derive(Debug, Clone)]
+/// A quadtree of charges approximating the field of far groups of charges
+/// with their total charge and dipole moment (Barnes–Hut)
+pub struct QuadTree {
+    nodes: Vec<Node>,
+    mode: Mode,
+    law: FieldLaw,
+    // A node is approximated when its size divided by its distance is less than theta
+    // (0 evaluates every charge exactly)
+    pub theta: f64,
+}
+
+impl QuadTree {
+    pub fn new(sources: &[(Vector, f64)], mode: Mode, law: FieldLaw, theta: f64) -> QuadTree {
+        // The square containing every charge
+        let mut min = Vector::new(0.0, 0.0);
+        let mut max = Vector::new(0.0, 0.0);
+        for (i, &(position, _)) in sources.iter().enumerate() {
+            if i == 0 {
+                min = position;
+                max = position;
+            }
+            min = Vector::new(min.x.min(position.x), min.y.min(position.y));
+            max = Vector::new(max.x.max(position.x), max.y.max(position.y));
+        }
+        let half_size = ((max.x - min.x).max(max.y - min.y) / 2.0).max(MIN_NODE_SIZE) * 1.01;
+
+        let mut tree = QuadTree {
+            nodes: Vec::new(),
+            mode,
+            law,
+            theta,
+        };
+        tree.build((min + max) / 2.0, half_size, sources.to_vec());
+        tree
+    }
+
+    /// Build the node holding `sources`, returning its index
+    fn build(&mut self, center: Vector, half_size: f64, sources: Vec<(Vector, f64)>) -> usize {
+        let mut charge = 0.0;
+        let mut absolute = 0.0;
+        let mut origin = Vector::new(0.0, 0.0);
+        for &(position, q) in &sources {
+            charge += q;
+            absolute += q.abs();
+            origin += position * q.abs();
+        }
+        let origin = if absolute > 0.0 {
+            origin / absolute
+        } else {
+            center
+        };
+
+        let mut dipole = Vector::new(0.0, 0.0);
+        for &(position, q) in &sources {
+            dipole += (position - origin) * q;
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            half_size,
+            charge,
+            origin,
+            dipole,
+            children: None,
+            sources: Vec::new(),
+        });
+
+        if sources.len() <= LEAF_SIZE || half_size < MIN_NODE_SIZE {
+            self.nodes[index].sources = sources;
+            return index;
+        }
+
+        // Split the charges in the four quadrants
+        let mut quadrants = vec![Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        for source in sources {
+            let right = source.0.x >= center.x;
+            let up = source.0.y >= center.y;
+            quadrants[right as usize + 2 * up as usize].push(source);
+        }
+
+        let quarter = half_size / 2.0;
+        let mut children = [0; 4];
+        for (i, quadrant) in quadrants.into_iter().enumerate() {
+            let offset = Vector::new(
+                if i % 2 == 1 { quarter } else { -quarter },
+                if i / 2 == 1 { quarter } else { -quarter },
+            );
+            children[i] = self.build(center + offset, quarter, quadrant);
+        }
+        self.nodes[index].children = Some(children);
+
+        index
+    }
+
+    /// The field & potential of all the charges at `position`
+    pub fn evaluate(&self, position: &Vector) -> (Vector, f64) {
+        let mut field = Vector::new(0.0, 0.0);
+        let mut potential = 0.0;
+
+        if self.nodes.is_empty() {
+            return (field, potential);
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let delta = position - node.origin;
+            let distance = delta.norm();
+
+            if node.children.is_some() && 2.0 * node.half_size < self.theta * distance {
+                // The node is far enough to be seen as a single charge and dipole
+                let (monopole_field, monopole_potential) =
+                    source_field(self.mode, self.law, node.charge, &delta);
+                let (dipole_field, dipole_potential) =
+                    get_dipole_field(self.mode, self.law, &node.dipole, &delta);
+
+                field += monopole_field + dipole_field;
+                potential += monopole_potential + dipole_potential;
+            } else if let Some(children) = node.children {
+                stack.extend(children.iter());
+            } else {
+                for &(source, charge) in &node.sources {
+                    let (source_field, source_potential) =
+                        source_field(self.mode, self.law, charge, &(position - source));
+                    field += source_field;
+                    potential += source_potential;
+                }
+            }
+        }
+
+        (field, potential)
+    }
+}
+
+#[inline]
+/// Calculate the field & potential of a `dipole` with distance `delta`
+fn get_dipole_field(mode: Mode, law: FieldLaw, dipole: &Vector, delta: &Vector) -> (Vector, f64) {
+    let distance = delta.norm();
+    if distance == 0.0 {
+        return (Vector::new(0.0, 0.0), 0.0);
+    }
+
+    let direction = delta / distance;
+    let projection = dipole.dot(&direction);
+
+    match (mode, law) {
+        (Mode::Electrostatic, FieldLaw::PointCharges) => (
+            (direction * 3.0 * projection - dipole) / distance.powi(3),
+            projection / distance.powi(2),
+        ),
+        (Mode::Electrostatic, FieldLaw::LineCharges) => (
+            (direction * 2.0 * projection - dipole) / distance.powi(2),
+            projection / distance,
+        ),
+        (Mode::Magnetostatic, _) => {
+            // The magnetic field is the field of line charges turned counterclockwise
+            let field = (direction * 2.0 * projection - dipole) / distance.powi(2);
+            (Vector::new(-field.y, field.x), projection / distance)
+        }
+    }
+}
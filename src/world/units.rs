@@ -0,0 +1,172 @@
+use super::FieldLaw;
+
+use std::f64;
+
+/// The permittivity of the vacuum, in F/m
+pub const VACUUM_PERMITTIVITY: f64 = 8.8541878128e-12;
+/// The charge of a proton, in C
+pub const ELEMENTARY_CHARGE: f64 = 1.602176634e-19;
+/// The speed of light in the vacuum, in m/s
+pub const SPEED_OF_LIGHT: f64 = 2.99792458e8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The physical charge of a unit of charge of the world
+pub enum ChargeUnit {
+    Coulomb,
+    /// The charge of a proton
+    Elementary,
+}
+
+impl ChargeUnit {
+    /// The charge of the unit, in C
+    pub fn coulombs(&self) -> f64 {
+        match *self {
+            ChargeUnit::Coulomb => 1.0,
+            ChargeUnit::Elementary => ELEMENTARY_CHARGE,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            ChargeUnit::Coulomb => "C",
+            ChargeUnit::Elementary => "e",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The physical scale of the world, converting the values of the simulation to SI units
+/// A point charge of 1 holds a `charge_unit`,
+/// a line charge of 1 holds a `charge_unit` on every `cell_size` of its length
+/// (the values of line charges are per cell of length, and so are their energies and forces,
+/// they are given per meter of length)
+/// The masses are in kg (per cell of length for line charges), which sets the unit of time
+/// The magnetostatic values have no physical units
+pub struct Units {
+    // The side of a tile, in m
+    pub cell_size: f64,
+    pub charge_unit: ChargeUnit,
+    // The permittivity of the space without dielectrics, in F/m
+    pub permittivity: f64,
+}
+
+impl Units {
+    /// Tiles of a nanometer holding elementary charges in the vacuum,
+    /// so a potential of 1 is about a volt
+    pub fn new() -> Units {
+        Units {
+            cell_size: 1e-9,
+            charge_unit: ChargeUnit::Elementary,
+            permittivity: VACUUM_PERMITTIVITY,
+        }
+    }
+
+    /// The volts in a unit of potential of the world
+    pub fn volts(&self, law: FieldLaw) -> f64 {
+        // The field spreads in three dimensions around a point and in two around a line
+        let angle = match law {
+            FieldLaw::PointCharges => 4.0 * f64::consts::PI,
+            FieldLaw::LineCharges => 2.0 * f64::consts::PI,
+        };
+
+        self.charge_unit.coulombs() / (angle * self.permittivity * self.cell_size)
+    }
+
+    /// A length in tiles, in m
+    pub fn length(&self, length: f64) -> f64 {
+        length * self.cell_size
+    }
+
+    /// A time, in s
+    pub fn time(&self, law: FieldLaw, time: f64) -> f64 {
+        // A unit of force gives a kg an acceleration of a tile per unit of time squared
+        time * self.cell_size / (self.charge_unit.coulombs() * self.volts(law)).sqrt()
+    }
+
+    /// The speed of light, in tiles per unit of time
+    /// (tiny masses are needed for the test particles to come close to it)
+    pub fn speed_of_light(&self, law: FieldLaw) -> f64 {
+        SPEED_OF_LIGHT * self.time(law, 1.0) / self.cell_size
+    }
+
+    /// The meters in the length holding the values of the charges:
+    /// a cell of length for line charges, nothing for point charges
+    fn depth(&self, law: FieldLaw) -> f64 {
+        match law {
+            FieldLaw::PointCharges => 1.0,
+            FieldLaw::LineCharges => self.cell_size,
+        }
+    }
+
+    /// A charge, in C (in C/m for line charges)
+    pub fn charge(&self, law: FieldLaw, charge: f64) -> f64 {
+        charge * self.charge_unit.coulombs() / self.depth(law)
+    }
+
+    /// A potential, in V
+    pub fn potential(&self, law: FieldLaw, potential: f64) -> f64 {
+        potential * self.volts(law)
+    }
+
+    /// An electric field, in V/m
+    pub fn field(&self, law: FieldLaw, field: f64) -> f64 {
+        field * self.volts(law) / self.cell_size
+    }
+
+    /// The flux of the displacement field through a curve, in C/m
+    /// (the charge enclosed by the curve on every meter of depth)
+    pub fn flux(&self, law: FieldLaw, flux: f64) -> f64 {
+        flux * self.volts(law) * self.permittivity
+    }
+
+    /// An energy, in J (in J/m for line charges)
+    pub fn energy(&self, law: FieldLaw, energy: f64) -> f64 {
+        energy * self.charge_unit.coulombs() * self.volts(law) / self.depth(law)
+    }
+
+    /// A force, in N (in N/m for line charges)
+    pub fn force(&self, law: FieldLaw, force: f64) -> f64 {
+        force * self.charge_unit.coulombs() * self.volts(law) / (self.cell_size * self.depth(law))
+    }
+
+    /// A capacitance, in F (in F/m for line charges)
+    pub fn capacitance(&self, law: FieldLaw, capacitance: f64) -> f64 {
+        capacitance * self.charge_unit.coulombs() / (self.volts(law) * self.depth(law))
+    }
+}
+
+/// The symbol of `unit` for a value held by the charges, per meter for line charges
+pub fn per_length(law: FieldLaw, unit: &str) -> String {
+    match law {
+        FieldLaw::PointCharges => unit.to_string(),
+        FieldLaw::LineCharges => format!("{}/m", unit),
+    }
+}
+
+/// `value` with three decimals and `unit` with the SI prefix that fits it
+pub fn format_si(value: f64, unit: &str) -> String {
+    let prefixes = [
+        (1e12, "T"),
+        (1e9, "G"),
+        (1e6, "M"),
+        (1e3, "k"),
+        (1.0, ""),
+        (1e-3, "m"),
+        (1e-6, "µ"),
+        (1e-9, "n"),
+        (1e-12, "p"),
+        (1e-15, "f"),
+        (1e-18, "a"),
+        (1e-21, "z"),
+    ];
+
+    if value == 0.0 || !value.is_finite() {
+        return format!("{:.3} {}", value, unit);
+    }
+
+    let &(scale, prefix) = prefixes
+        .iter()
+        .find(|&&(scale, _)| value.abs() >= scale)
+        .unwrap_or(&prefixes[prefixes.len() - 1]);
+    format!("{:.3} {}{}", value / scale, prefix, unit)
+}
@@ -0,0 +1,100 @@
+use world::contours::{contour, even_levels};
+use Vector;
+
+use std::f64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A rectangle of the world sampled on `columns × rows` points,
+/// like the pixels of the screen showing it
+pub struct Viewport {
+    // The lower left and the upper right corners, in tiles' coordinates
+    pub min: Vector,
+    pub max: Vector,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl Viewport {
+    pub fn new(min: Vector, max: Vector, columns: usize, rows: usize) -> Viewport {
+        Viewport {
+            min,
+            max,
+            columns,
+            rows,
+        }
+    }
+
+    /// The size of the area around a sample, in tiles
+    pub fn pixel(&self) -> Vector {
+        Vector::new(
+            (self.max.x - self.min.x) / self.columns as f64,
+            (self.max.y - self.min.y) / self.rows as f64,
+        )
+    }
+
+    /// The position of the sample at `column` and `row`, the rows going up from the bottom
+    pub fn point(&self, column: usize, row: usize) -> Vector {
+        let pixel = self.pixel();
+        self.min + Vector::new(
+            (column as f64 + 0.5) * pixel.x,
+            (row as f64 + 0.5) * pixel.y,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The field and the potential sampled on the points of a viewport
+pub struct ViewField {
+    pub viewport: Viewport,
+    // The samples row by row, from the bottom
+    pub cells: Vec<(Vector, f64)>,
+}
+
+impl ViewField {
+    pub fn get(&self, column: usize, row: usize) -> &(Vector, f64) {
+        &self.cells[row * self.viewport.columns + column]
+    }
+
+    /// The lowest and highest potential of the samples
+    pub fn potential_range(&self) -> (f64, f64) {
+        self.cells
+            .iter()
+            .map(|&(_, potential)| potential)
+            .filter(|potential| potential.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), potential| {
+                (min.min(potential), max.max(potential))
+            })
+    }
+
+    /// The equipotential curves at `level`, as polylines in tiles' coordinates
+    pub fn equipotentials(&self, level: f64) -> Vec<Vec<Vector>> {
+        let viewport = &self.viewport;
+        let pixel = viewport.pixel();
+        let potentials: Vec<f64> = self.cells.iter().map(|&(_, potential)| potential).collect();
+
+        // The samples are on the centers of the pixels
+        contour(&potentials, viewport.columns, viewport.rows, level)
+            .into_iter()
+            .map(|polyline| {
+                polyline
+                    .iter()
+                    .map(|point| {
+                        viewport.min + Vector::new(
+                            (point.x + 0.5) * pixel.x,
+                            (point.y + 0.5) * pixel.y,
+                        )
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The equipotential curves at every multiple of `spacing`, with their level
+    pub fn even_equipotentials(&self, spacing: f64) -> Vec<(f64, Vec<Vec<Vector>>)> {
+        let (min, max) = self.potential_range();
+        even_levels(min, max, spacing)
+            .into_iter()
+            .map(|level| (level, self.equipotentials(level)))
+            .collect()
+    }
+}